@@ -0,0 +1,322 @@
+use std::{
+    fs::{self, File},
+    io::{BufReader, Error, ErrorKind, Read, Result},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::core::utils::{constant_time_eq, timestamp, to_hex, write_json_atomic};
+
+pub const KEYS_PATH: &str = "./key/keys.json";
+/// Single plaintext key used before named keys existed, kept next to the key file.
+/// It is imported and removed the first time the key file is loaded.
+const LEGACY_KEY_FILE_NAME: &str = "pass.key";
+const LEGACY_KEY_NAME: &str = "default";
+
+/// What a key may do. Each role includes the permissions of the ones before it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    ReadOnly,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Role> {
+        match value {
+            "read-only" => Some(Role::ReadOnly),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Keys created before roles existed had full access.
+fn legacy_role() -> Role {
+    Role::Admin
+}
+
+/// A named API key. Only a salted SHA-256 hash of the secret is ever stored.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub name: String,
+    #[serde(default = "legacy_role")]
+    pub role: Role,
+    salt: String,
+    hash: String,
+    pub created: String,
+}
+
+impl ApiKey {
+    /// Creates a key record and returns it together with the secret to hand out.
+    fn generate(name: &str, role: Role) -> Result<(Self, String)> {
+        let secret = format!("folio_{}", to_hex(&random_bytes()?));
+        Ok((ApiKey::from_secret(name, role, &secret)?, secret))
+    }
+
+    fn from_secret(name: &str, role: Role, secret: &str) -> Result<Self> {
+        let salt = to_hex(&random_bytes()?);
+        Ok(ApiKey {
+            name: name.to_string(),
+            role,
+            hash: hash_secret(&salt, secret),
+            salt,
+            created: timestamp(),
+        })
+    }
+
+    /// Identifies this exact key. It changes when the key is rotated, or revoked
+    /// and added again, even under the same name and role.
+    pub fn fingerprint(&self) -> String {
+        to_hex(&Sha256::digest(format!("{}:{}", self.salt, self.hash)))[..16].to_string()
+    }
+
+    fn matches(&self, secret: &str) -> bool {
+        constant_time_eq(
+            hash_secret(&self.salt, secret).as_bytes(),
+            self.hash.as_bytes(),
+        )
+    }
+}
+
+pub struct KeyStore {
+    path: String,
+    keys: Vec<ApiKey>,
+}
+
+impl KeyStore {
+    /// Loads the key file, importing the legacy `pass.key` if there is no key file yet.
+    pub fn load(path: &str) -> Result<Self> {
+        match File::open(path) {
+            Ok(file) => {
+                let mut buffer = Vec::new();
+                BufReader::new(file).read_to_end(&mut buffer)?;
+                match serde_json::from_slice::<Vec<ApiKey>>(&buffer) {
+                    Ok(keys) => Ok(KeyStore {
+                        path: path.to_string(),
+                        keys,
+                    }),
+                    Err(error) => {
+                        eprintln!("Key file structure is incorrect: {}", error);
+                        Err(error.into())
+                    }
+                }
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                let mut store = KeyStore {
+                    path: path.to_string(),
+                    keys: Vec::new(),
+                };
+                store.import_legacy_key()?;
+                Ok(store)
+            }
+            Err(error) => {
+                eprintln!("Could not open key file: {}", error);
+                Err(error)
+            }
+        }
+    }
+
+    fn import_legacy_key(&mut self) -> Result<()> {
+        let legacy_path = Path::new(&self.path).with_file_name(LEGACY_KEY_FILE_NAME);
+        let secret = match fs::read_to_string(&legacy_path) {
+            Ok(secret) => secret,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        println!(
+            "Importing legacy key file as API key \"{}\"...",
+            LEGACY_KEY_NAME
+        );
+        self.keys.push(ApiKey::from_secret(
+            LEGACY_KEY_NAME,
+            legacy_role(),
+            &secret,
+        )?);
+        self.save()?;
+        fs::remove_file(&legacy_path)?;
+        println!("Legacy key file removed.");
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json_atomic(&self.path, &self.keys)
+    }
+
+    pub fn keys(&self) -> &[ApiKey] {
+        &self.keys
+    }
+
+    /// Finds the key a bearer token belongs to.
+    pub fn verify(&self, secret: &str) -> Option<&ApiKey> {
+        // Check every key so timing does not reveal how many keys were tried.
+        self.keys.iter().fold(None, |found, key| {
+            if key.matches(secret) {
+                Some(key)
+            } else {
+                found
+            }
+        })
+    }
+
+    /// Adds a key and returns its secret, which is not recoverable afterwards.
+    pub fn add(&mut self, name: &str, role: Role) -> Result<String> {
+        if self.keys.iter().any(|key| key.name == name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("API key \"{}\" already exists", name),
+            ));
+        }
+        let (key, secret) = ApiKey::generate(name, role)?;
+        self.keys.push(key);
+        self.save()?;
+        Ok(secret)
+    }
+
+    pub fn revoke(&mut self, name: &str) -> Result<()> {
+        let count = self.keys.len();
+        self.keys.retain(|key| key.name != name);
+        if self.keys.len() == count {
+            return Err(unknown_key(name));
+        }
+        self.save()
+    }
+
+    /// Replaces a key's secret, invalidating the old one. The role is kept.
+    pub fn rotate(&mut self, name: &str) -> Result<String> {
+        let Some(index) = self.keys.iter().position(|key| key.name == name) else {
+            return Err(unknown_key(name));
+        };
+        let (key, secret) = ApiKey::generate(name, self.keys[index].role)?;
+        self.keys[index] = key;
+        self.save()?;
+        Ok(secret)
+    }
+}
+
+/// Handles `folio keys <list|add|revoke|rotate> [name] [role]`.
+pub fn run_command(args: &[String]) -> Result<()> {
+    let mut store = KeyStore::load(KEYS_PATH)?;
+    match (args.first().map(String::as_str), args.get(1)) {
+        (Some("list"), _) => {
+            for key in store.keys() {
+                println!(
+                    "{}\t{}\t(created {})",
+                    key.name,
+                    key.role.as_str(),
+                    key.created
+                );
+            }
+            Ok(())
+        }
+        (Some("add"), Some(name)) => {
+            let role = match args.get(2) {
+                Some(role) => Role::parse(role).ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("Unknown role \"{}\", expected read-only, editor or admin", role),
+                    )
+                })?,
+                None => Role::Editor,
+            };
+            let secret = store.add(name, role)?;
+            println!(
+                "Added API key \"{}\". Store it now, it will not be shown again:",
+                name
+            );
+            println!("{}", secret);
+            Ok(())
+        }
+        (Some("revoke"), Some(name)) => {
+            store.revoke(name)?;
+            println!("Revoked API key \"{}\".", name);
+            Ok(())
+        }
+        (Some("rotate"), Some(name)) => {
+            let secret = store.rotate(name)?;
+            println!(
+                "Rotated API key \"{}\". Store it now, it will not be shown again:",
+                name
+            );
+            println!("{}", secret);
+            Ok(())
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Usage: folio keys <list | add NAME [read-only|editor|admin] | revoke NAME | rotate NAME>",
+        )),
+    }
+}
+
+fn unknown_key(name: &str) -> Error {
+    Error::new(
+        ErrorKind::NotFound,
+        format!("No API key named \"{}\"", name),
+    )
+}
+
+fn random_bytes() -> Result<[u8; 32]> {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).map_err(Error::other)?;
+    Ok(bytes)
+}
+
+fn hash_secret(salt: &str, secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(secret.as_bytes());
+    to_hex(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{KeyStore, Role};
+    use crate::core::utils::TempDir;
+
+    fn temp_store() -> (TempDir, KeyStore) {
+        let dir = TempDir::create();
+        let store = KeyStore::load(&dir.path("keys.json")).unwrap();
+        (dir, store)
+    }
+
+    #[test]
+    fn verifies_only_current_secrets() {
+        let (_dir, mut store) = temp_store();
+        let secret = store.add("editor", Role::Editor).unwrap();
+        assert_eq!(store.verify(&secret).unwrap().name, "editor");
+        assert!(store.verify("folio_wrong").is_none());
+
+        let rotated = store.rotate("editor").unwrap();
+        assert!(store.verify(&secret).is_none());
+        assert_eq!(store.verify(&rotated).unwrap().role, Role::Editor);
+
+        store.revoke("editor").unwrap();
+        assert!(store.verify(&rotated).is_none());
+    }
+
+    #[test]
+    fn never_stores_plaintext_secrets() {
+        let (_dir, mut store) = temp_store();
+        let secret = store.add("owner", Role::Admin).unwrap();
+        let saved = fs::read_to_string(&store.path).unwrap();
+        assert!(!saved.contains(&secret));
+        assert!(KeyStore::load(&store.path)
+            .unwrap()
+            .verify(&secret)
+            .is_some());
+    }
+}
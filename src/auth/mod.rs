@@ -0,0 +1,64 @@
+use std::io::Result;
+
+use actix_web::{dev::ServiceRequest, web::Data, HttpMessage};
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+
+use crate::error::ApiError;
+use keys::Role;
+use session::{Claims, Sessions};
+
+pub mod keys;
+pub mod session;
+
+/// Validates a session token's signature, expiry and revocation.
+pub fn check_auth(sessions: &Sessions, token: &str) -> Result<Claims> {
+    sessions.verify(token).inspect_err(|error| {
+        eprintln!("Failed to authorize: {}", error);
+    })
+}
+
+/// Validator for `HttpAuthentication` wrapping every protected route. Rejects
+/// sessions below the route's `required` role; on success the session's `Claims`
+/// are stored in the request extensions.
+pub async fn validator(
+    req: ServiceRequest,
+    credentials: Option<BearerAuth>,
+    required: Role,
+) -> std::result::Result<ServiceRequest, (actix_web::Error, ServiceRequest)> {
+    let Some(credentials) = credentials else {
+        return Err((
+            ApiError::Unauthorized("Missing bearer token.".to_string()).into(),
+            req,
+        ));
+    };
+    let Some(sessions) = req.app_data::<Data<Sessions>>().cloned() else {
+        eprintln!("Session store is not configured.");
+        return Err((
+            ApiError::Unavailable("Authentication is temporarily unavailable.".to_string()).into(),
+            req,
+        ));
+    };
+    match check_auth(&sessions, credentials.token()) {
+        Ok(claims) if claims.role < required => {
+            eprintln!(
+                "Session for \"{}\" ({}) lacks the {} role",
+                claims.sub,
+                claims.role.as_str(),
+                required.as_str()
+            );
+            Err((
+                ApiError::Forbidden(format!(
+                    "This action requires the {} role.",
+                    required.as_str()
+                ))
+                .into(),
+                req,
+            ))
+        }
+        Ok(claims) => {
+            req.extensions_mut().insert(claims);
+            Ok(req)
+        }
+        Err(error) => Err((ApiError::Unauthorized(error.to_string()).into(), req)),
+    }
+}
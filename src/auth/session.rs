@@ -0,0 +1,215 @@
+use std::{
+    collections::HashMap,
+    io::{Error, ErrorKind, Result},
+    sync::{Mutex, PoisonError},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::keys::{ApiKey, KeyStore, Role};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Claims carried by a session token.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Claims {
+    /// Name of the API key the session was opened with.
+    pub sub: String,
+    pub role: Role,
+    /// Fingerprint of the API key, so sessions end with the key that opened them.
+    pub key: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+}
+
+/// A freshly issued session token.
+#[derive(Serialize, Debug)]
+pub struct Session {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+}
+
+/// Issues and validates short-lived HS256 session tokens in JWT format.
+///
+/// The signing secret is generated at startup and never leaves the process, so
+/// restarting the server ends every session along with the revocation list.
+pub struct Sessions {
+    secret: [u8; 32],
+    /// Key file checked on refresh, so revoked keys cannot keep sessions alive.
+    keys_path: String,
+    ttl_seconds: i64,
+    /// Revoked token ids, kept until the token would have expired anyway.
+    revoked: Mutex<HashMap<String, i64>>,
+}
+
+impl Sessions {
+    pub fn new(keys_path: &str, ttl_minutes: u16) -> Result<Self> {
+        let mut secret = [0u8; 32];
+        getrandom::fill(&mut secret).map_err(Error::other)?;
+        Ok(Sessions {
+            secret,
+            keys_path: keys_path.to_string(),
+            ttl_seconds: i64::from(ttl_minutes) * 60,
+            revoked: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Opens a session for the API key `secret` belongs to. Fails with
+    /// `ErrorKind::PermissionDenied` for unknown keys; any other error means
+    /// the key file itself could not be read.
+    pub fn login(&self, secret: &str) -> Result<Session> {
+        let keys = KeyStore::load(&self.keys_path)?;
+        let Some(key) = keys.verify(secret) else {
+            let error = denied("Authorization token is incorrect.");
+            eprintln!("Failed to authorize: {}", error);
+            return Err(error);
+        };
+        println!("Accepted API key \"{}\"", key.name);
+        self.issue(key)
+    }
+
+    /// Replaces a valid session with a new one, revoking the old token. Fails
+    /// with `ErrorKind::PermissionDenied` if the session's API key has since
+    /// been revoked, rotated or given another role.
+    pub fn refresh(&self, claims: &Claims) -> Result<Session> {
+        let keys = KeyStore::load(&self.keys_path)?;
+        let Some(key) = keys.keys().iter().find(|key| {
+            key.name == claims.sub && key.role == claims.role && key.fingerprint() == claims.key
+        }) else {
+            return Err(denied("API key has been revoked or changed."));
+        };
+        let session = self.issue(key)?;
+        self.revoke(claims);
+        Ok(session)
+    }
+
+    pub fn revoke(&self, claims: &Claims) {
+        let now = Utc::now().timestamp();
+        let mut revoked = self.revoked.lock().unwrap_or_else(PoisonError::into_inner);
+        revoked.retain(|_, exp| *exp > now);
+        revoked.insert(claims.jti.clone(), claims.exp);
+    }
+
+    /// Checks a token's signature, expiry and revocation. Fails with
+    /// `ErrorKind::PermissionDenied` for any token that should not be accepted.
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [header, payload, signature] = parts[..] else {
+            return Err(denied("Malformed session token."));
+        };
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| denied("Malformed session token."))?;
+        self.mac(header, payload)?
+            .verify_slice(&signature)
+            .map_err(|_| denied("Invalid session token signature."))?;
+        let claims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|payload| serde_json::from_slice::<Claims>(&payload).ok())
+            .ok_or_else(|| denied("Malformed session token."))?;
+        if claims.exp <= Utc::now().timestamp() {
+            return Err(denied("Session token has expired."));
+        }
+        let revoked = self.revoked.lock().unwrap_or_else(PoisonError::into_inner);
+        if revoked.contains_key(&claims.jti) {
+            return Err(denied("Session token has been revoked."));
+        }
+        Ok(claims)
+    }
+
+    /// Opens a session for an API key that has already been verified.
+    pub fn issue(&self, key: &ApiKey) -> Result<Session> {
+        let now = Utc::now().timestamp();
+        let claims = Claims {
+            sub: key.name.clone(),
+            role: key.role,
+            key: key.fingerprint(),
+            iat: now,
+            exp: now + self.ttl_seconds,
+            jti: Uuid::new_v4().to_string(),
+        };
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "HS256", "typ": "JWT" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let signature =
+            URL_SAFE_NO_PAD.encode(self.mac(&header, &payload)?.finalize().into_bytes());
+        Ok(Session {
+            token: format!("{}.{}.{}", header, payload, signature),
+            token_type: "Bearer",
+            expires_in: self.ttl_seconds,
+        })
+    }
+
+    fn mac(&self, header: &str, payload: &str) -> Result<HmacSha256> {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).map_err(Error::other)?;
+        mac.update(header.as_bytes());
+        mac.update(b".");
+        mac.update(payload.as_bytes());
+        Ok(mac)
+    }
+}
+
+fn denied(message: &str) -> Error {
+    Error::new(ErrorKind::PermissionDenied, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Claims, Sessions};
+    use crate::{
+        auth::keys::{KeyStore, Role},
+        core::utils::TempDir,
+    };
+
+    /// Sessions backed by a fresh key file holding an editor key, and the
+    /// key's secret.
+    fn editor_sessions(ttl_minutes: u16) -> (TempDir, Sessions, String) {
+        let dir = TempDir::create();
+        let path = dir.path("keys.json");
+        let secret = KeyStore::load(&path)
+            .unwrap()
+            .add("editor", Role::Editor)
+            .unwrap();
+        (dir, Sessions::new(&path, ttl_minutes).unwrap(), secret)
+    }
+
+    fn claims(sessions: &Sessions, secret: &str) -> (String, Claims) {
+        let session = sessions.login(secret).unwrap();
+        let claims = sessions.verify(&session.token).unwrap();
+        (session.token, claims)
+    }
+
+    #[test]
+    fn rejects_tampered_tokens() {
+        let (_dir, sessions, secret) = editor_sessions(15);
+        let (token, claims) = claims(&sessions, &secret);
+        assert_eq!(claims.sub, "editor");
+
+        let mut tampered = token.clone();
+        tampered.insert(token.find('.').unwrap() + 2, 'x');
+        assert!(sessions.verify(&tampered).is_err());
+        assert!(editor_sessions(15).1.verify(&token).is_err());
+        assert!(sessions.login("folio_unknown").is_err());
+    }
+
+    #[test]
+    fn rejects_expired_and_revoked_tokens() {
+        let (_dir, sessions, secret) = editor_sessions(0);
+        let token = sessions.login(&secret).unwrap().token;
+        assert!(sessions.verify(&token).is_err());
+
+        let (_dir, sessions, secret) = editor_sessions(15);
+        let (token, claims) = claims(&sessions, &secret);
+        let refreshed = sessions.refresh(&claims).unwrap();
+        assert!(sessions.verify(&token).is_err());
+        assert!(sessions.verify(&refreshed.token).is_ok());
+    }
+}
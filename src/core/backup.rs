@@ -0,0 +1,378 @@
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs,
+    io::{Error, ErrorKind, Result},
+};
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::core::{
+    data::{parse_collections, Collection},
+    settings::Settings,
+    store::Store,
+    utils::{to_hex, TIMESTAMP_FORMAT},
+};
+
+/// Time format used in snapshot file names, precise enough that snapshots
+/// taken in the same second do not collide.
+const FILE_TIME_FORMAT: &str = "%Y%m%dT%H%M%S%3fZ";
+/// Length of a time written with `FILE_TIME_FORMAT`.
+const FILE_TIME_LENGTH: usize = 19;
+/// Reason of the snapshots taken on a timer, the only ones thinned out per
+/// period.
+pub const SCHEDULED: &str = "scheduled";
+/// Reason of the snapshot taken before a backup is restored. These are never
+/// pruned, so a restore can always be undone.
+pub const PRE_RESTORE: &str = "pre-restore";
+
+/// How many scheduled snapshots to keep per hour, day and ISO week, counting
+/// back from the newest. A snapshot is kept if any of the three rules keeps it.
+/// Snapshots taken before a change are kept for `event_days` instead, however
+/// many were taken in the same period, and `PRE_RESTORE` ones are kept for good.
+#[derive(Clone, Copy, Debug)]
+pub struct Retention {
+    pub hourly: u16,
+    pub daily: u16,
+    pub weekly: u16,
+    pub event_days: u16,
+}
+
+/// One timestamped copy of the projects data in the backup directory.
+#[derive(Serialize, Clone, Debug)]
+pub struct Snapshot {
+    /// File name inside the backup directory.
+    pub name: String,
+    /// Why the snapshot was taken, e.g. `scheduled` or `delete`.
+    pub reason: String,
+    pub created_at: String,
+    pub size: u64,
+    /// SHA-256 of the file as written, from its `.sha256` companion file.
+    pub checksum: Option<String>,
+    #[serde(skip)]
+    time: DateTime<Utc>,
+}
+
+/// Timestamped, checksummed snapshots of the store, thinned out by a
+/// retention policy each time a new one is taken.
+pub struct Backups {
+    dir: String,
+    /// Prefix of every snapshot file name, the projects file name.
+    prefix: String,
+    retention: Retention,
+}
+
+impl Backups {
+    pub fn new(dir: &str, prefix: &str, retention: Retention) -> Self {
+        Backups {
+            dir: dir.to_string(),
+            prefix: prefix.to_string(),
+            retention,
+        }
+    }
+
+    /// Snapshots kept in the backup directory under the projects file name.
+    pub fn open(settings: &Settings) -> Self {
+        Backups::new(
+            &settings.local_backup_path.value,
+            &settings.projects_file_name.value,
+            Retention {
+                hourly: settings.backup_keep_hourly.value,
+                daily: settings.backup_keep_daily.value,
+                weekly: settings.backup_keep_weekly.value,
+                event_days: settings.backup_keep_event_days.value,
+            },
+        )
+    }
+
+    /// Writes a snapshot of every collection in `store` with its checksum, then
+    /// removes the snapshots the retention policy no longer keeps.
+    pub fn create(&self, store: &dyn Store, reason: &str) -> Result<Snapshot> {
+        if reason.is_empty() || !reason.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("\"{}\" is not a valid backup reason", reason),
+            ));
+        }
+        let time = Utc::now();
+        let name = format!(
+            "{}-{}-{}.json",
+            self.prefix,
+            time.format(FILE_TIME_FORMAT),
+            reason
+        );
+        let path = self.path(&name);
+        store.snapshot(&path)?;
+        let bytes = fs::read(&path)?;
+        let checksum = to_hex(&Sha256::digest(&bytes));
+        // Same layout as `sha256sum`, so a snapshot can be checked by hand.
+        fs::write(
+            format!("{}.sha256", path),
+            format!("{}  {}\n", checksum, name),
+        )?;
+        if let Err(error) = self.prune() {
+            eprintln!("Failed to remove old backups: {}", error);
+        }
+        Ok(Snapshot {
+            name,
+            reason: reason.to_string(),
+            created_at: time.format(TIMESTAMP_FORMAT).to_string(),
+            size: bytes.len() as u64,
+            checksum: Some(checksum),
+            time,
+        })
+    }
+
+    /// Every snapshot in the backup directory, newest first.
+    pub fn list(&self) -> Result<Vec<Snapshot>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let Some((time, reason)) = self.parse_name(&name) else {
+                continue;
+            };
+            let checksum = fs::read_to_string(self.path(&format!("{}.sha256", name)))
+                .ok()
+                .and_then(|line| line.split_whitespace().next().map(str::to_string));
+            snapshots.push(Snapshot {
+                reason: reason.to_string(),
+                created_at: time.format(TIMESTAMP_FORMAT).to_string(),
+                size: entry.metadata()?.len(),
+                checksum,
+                time,
+                name,
+            });
+        }
+        snapshots.sort_by_key(|snapshot| Reverse(snapshot.time));
+        Ok(snapshots)
+    }
+
+    /// Deletes the snapshots the retention policy does not keep and returns them.
+    /// The newest snapshot and those taken before a restore are always kept.
+    pub fn prune(&self) -> Result<Vec<Snapshot>> {
+        let snapshots = self.list()?;
+        let times: Vec<DateTime<Utc>> = snapshots
+            .iter()
+            .filter(|snapshot| snapshot.reason == SCHEDULED)
+            .map(|snapshot| snapshot.time)
+            .collect();
+        let mut scheduled = retained(&times, self.retention).into_iter();
+        let cutoff = Utc::now() - Duration::days(i64::from(self.retention.event_days));
+        let mut removed = Vec::new();
+        for (index, snapshot) in snapshots.into_iter().enumerate() {
+            let kept = if snapshot.reason == SCHEDULED {
+                scheduled.next().unwrap_or(true)
+            } else {
+                snapshot.time > cutoff
+            };
+            if kept || index == 0 || snapshot.reason == PRE_RESTORE {
+                continue;
+            }
+            fs::remove_file(self.path(&snapshot.name))?;
+            let _ = fs::remove_file(self.path(&format!("{}.sha256", snapshot.name)));
+            removed.push(snapshot);
+        }
+        Ok(removed)
+    }
+
+    /// The snapshot with file name `name`. Only names of listed snapshots are
+    /// accepted, so the name cannot point outside the backup directory.
+    pub fn get(&self, name: &str) -> Result<Option<Snapshot>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|snapshot| snapshot.name == name))
+    }
+
+    /// The snapshot file's contents. Fails with `ErrorKind::InvalidData` if they
+    /// no longer match the recorded checksum.
+    pub fn read(&self, snapshot: &Snapshot) -> Result<Vec<u8>> {
+        let bytes = fs::read(self.path(&snapshot.name))?;
+        if let Some(checksum) = &snapshot.checksum {
+            if to_hex(&Sha256::digest(&bytes)) != *checksum {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Backup {} does not match its checksum", snapshot.name),
+                ));
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// The collections saved in a snapshot, after checking its checksum.
+    pub fn load(&self, snapshot: &Snapshot) -> Result<Vec<Collection>> {
+        let bytes = self.read(snapshot)?;
+        let (collections, _) = parse_collections(&bytes).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Backup {} is not a projects file: {}", snapshot.name, error),
+            )
+        })?;
+        Ok(collections)
+    }
+
+    /// When the newest snapshot was taken, if there is one.
+    pub fn latest(&self) -> Result<Option<DateTime<Utc>>> {
+        Ok(self.list()?.first().map(|snapshot| snapshot.time))
+    }
+
+    fn path(&self, name: &str) -> String {
+        format!("{}/{}", self.dir, name)
+    }
+
+    /// Splits a snapshot file name into its time and reason. `None` for any
+    /// other file, including the single backup written by older versions.
+    fn parse_name<'a>(&self, name: &'a str) -> Option<(DateTime<Utc>, &'a str)> {
+        let rest = name
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix('-')?
+            .strip_suffix(".json")?;
+        let time = rest.get(..FILE_TIME_LENGTH)?;
+        let reason = rest.get(FILE_TIME_LENGTH..)?.strip_prefix('-')?;
+        let time = NaiveDateTime::parse_from_str(time, FILE_TIME_FORMAT).ok()?;
+        Some((time.and_utc(), reason))
+    }
+}
+
+/// Which of `times`, ordered newest first, the retention policy keeps. Within
+/// each rule the newest snapshot of each period is kept, for as many of the
+/// most recent periods as the rule allows. The newest snapshot is always kept.
+fn retained(times: &[DateTime<Utc>], retention: Retention) -> Vec<bool> {
+    let mut kept = vec![false; times.len()];
+    if let Some(newest) = kept.first_mut() {
+        *newest = true;
+    }
+    for (count, period) in [
+        (retention.hourly, "%Y-%m-%d %H"),
+        (retention.daily, "%Y-%m-%d"),
+        (retention.weekly, "%G-W%V"),
+    ] {
+        let mut periods = HashSet::new();
+        for (index, time) in times.iter().enumerate() {
+            if periods.len() >= usize::from(count) {
+                break;
+            }
+            if periods.insert(time.format(period).to_string()) {
+                kept[index] = true;
+            }
+        }
+    }
+    kept
+}
+
+/// Keeps only the newest snapshot and those taken before a restore.
+#[cfg(test)]
+impl Retention {
+    pub const NONE: Retention = Retention {
+        hourly: 0,
+        daily: 0,
+        weekly: 0,
+        event_days: 0,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+
+    use chrono::{Duration, TimeZone, Utc};
+
+    use super::{retained, Backups, Retention, PRE_RESTORE, SCHEDULED};
+    use crate::core::{
+        data::{write_local_db, Collection},
+        store::json::JsonStore,
+        utils::TempDir,
+    };
+
+    /// A store holding one collection and backups of it under `retention`.
+    fn backed_up_store(dir: &TempDir, retention: Retention) -> (JsonStore, Backups) {
+        let path = dir.path("projects.json");
+        write_local_db(&path, vec![Collection::default(Vec::new())]).unwrap();
+        let backups = Backups::new(&dir.path("backup"), "projects", retention);
+        (JsonStore::new(&path), backups)
+    }
+
+    #[test]
+    fn keeps_newest_snapshot_per_period() {
+        let newest = Utc.with_ymd_and_hms(2024, 5, 15, 12, 30, 0).unwrap();
+        // Every half hour for three days, newest first.
+        let times: Vec<_> = (0..144)
+            .map(|step| newest - Duration::minutes(30 * step))
+            .collect();
+        let retention = Retention {
+            hourly: 3,
+            daily: 2,
+            ..Retention::NONE
+        };
+        let kept: Vec<_> = times
+            .iter()
+            .zip(retained(&times, retention))
+            .filter(|(_, kept)| *kept)
+            .map(|(time, _)| time.format("%d %H:%M").to_string())
+            .collect();
+        assert_eq!(kept, ["15 12:30", "15 11:30", "15 10:30", "14 23:30"]);
+    }
+
+    #[test]
+    fn writes_checksummed_snapshots() {
+        let dir = TempDir::create();
+        let (store, backups) = backed_up_store(&dir, Retention::NONE);
+
+        let restore = backups.create(&store, PRE_RESTORE).unwrap();
+        // Snapshot names are only unique to the millisecond.
+        thread::sleep(std::time::Duration::from_millis(5));
+        let first = backups.create(&store, "startup").unwrap();
+        thread::sleep(std::time::Duration::from_millis(5));
+        let second = backups.create(&store, "delete").unwrap();
+        assert!(backups.create(&store, "../escape").is_err());
+        assert_eq!(first.checksum, second.checksum);
+
+        // Only the newest snapshot and the one taken before a restore survive
+        // a policy that keeps nothing else.
+        let snapshots = backups.list().unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0].name, second.name);
+        assert_eq!(snapshots[1].name, restore.name);
+        assert_eq!(snapshots[0].reason, "delete");
+        assert_eq!(snapshots[0].checksum, second.checksum);
+        assert_eq!(backups.load(&snapshots[0]).unwrap().len(), 1);
+
+        fs::write(dir.path(&format!("backup/{}", second.name)), "[]").unwrap();
+        assert!(backups.load(&snapshots[0]).is_err());
+        assert!(backups.get("../projects.json").unwrap().is_none());
+    }
+
+    #[test]
+    fn keeps_every_recent_pre_change_snapshot() {
+        let dir = TempDir::create();
+        let retention = Retention {
+            hourly: 1,
+            event_days: 1,
+            ..Retention::NONE
+        };
+        let (store, backups) = backed_up_store(&dir, retention);
+
+        let mut names = Vec::new();
+        for reason in [SCHEDULED, "delete", "delete", SCHEDULED] {
+            names.push(backups.create(&store, reason).unwrap().name);
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        // Both deletes in this hour survive; only one scheduled snapshot does.
+        let kept: Vec<_> = backups
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|snapshot| snapshot.name)
+            .collect();
+        assert_eq!(kept, [&names[3], &names[2], &names[1]].map(String::clone));
+    }
+}
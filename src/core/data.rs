@@ -1,16 +1,38 @@
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter, Error, ErrorKind, Read, Result},
+    fs::{self, File},
+    io::{BufReader, Error, ErrorKind, Read, Result},
 };
 
-use chrono::Local;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
 
 use awc::Client;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+use crate::core::utils::{timestamp, write_json_atomic};
+
+/// Stand-in used for images that have not been uploaded yet.
+pub const PLACEHOLDER: &str = "n/a";
+
+/// Where a collection is in the publishing workflow. Only published
+/// collections are served to readers without a session.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The default, so new collections stay private until published.
+    #[default]
+    Draft,
+    /// Published automatically once `publish_at` has passed.
+    Scheduled,
+    Published,
+    Archived,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Collection {
-    pub id: u32,
+    /// Assigned by the server when the collection is created.
+    #[serde(default)]
+    pub id: Uuid,
     pub client: String,
     pub client_logo: String,
     pub accent_color: String,
@@ -20,85 +42,159 @@ pub struct Collection {
     pub keypoints: Vec<Keypoint>,
     pub summary: String,
     pub text_fields: Vec<TextField>,
+    /// Display order among all collections; changed only through reordering.
+    #[serde(default)]
+    pub position: u32,
+    #[serde(default)]
     pub last_modified: String,
+    /// Incremented on every change; the basis of the collection's `ETag`.
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(default)]
+    pub status: Status,
+    /// When a scheduled collection goes live, or when a published one did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish_at: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Keypoint {
+    /// Unique within the collection. Assigned by the server when created on its own.
+    #[serde(default)]
     pub id: u32,
+    /// Display order within the collection.
+    #[serde(default)]
+    pub position: u32,
     pub featured: Vec<String>,
     pub title: String,
     pub summary: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct TextField {
+    /// Unique within the collection. Assigned by the server when created on its own.
+    #[serde(default)]
     pub id: u32,
     pub name: String,
     pub value: String,
 }
 
-impl TextField {
-    pub fn new(id: u32, name: String, value: String) -> Self {
-        TextField {
-            id: id,
-            name: name,
-            value: value,
-        }
+/// An item stored inside a collection and addressed by an id unique within it.
+pub trait Nested: Serialize + DeserializeOwned + Clone + 'static {
+    /// Name used in messages.
+    const NAME: &'static str;
+    /// Path segment of the item's routes below `/v1/projects/{id}`.
+    const PATH: &'static str;
+
+    fn id(&self) -> u32;
+    fn set_id(&mut self, id: u32);
+    fn items(collection: &mut Collection) -> &mut Vec<Self>;
+
+    /// Items without an explicit position are ordered by their place in the list.
+    fn position(&self) -> u32 {
+        0
     }
+
+    fn set_position(&mut self, _position: u32) {}
 }
 
-impl Collection {
-    fn new(
-        id: u32,
-        client: String,
-        client_logo: String,
-        accent_color: String,
-        title: String,
-        tags: Vec<String>,
-        featured: String,
-        keypoints: Vec<Keypoint>,
-        summary: String,
-        last_modified: String,
-    ) -> Self {
-        Collection {
-            id: id,
-            client: client,
-            client_logo: client_logo,
-            accent_color: accent_color,
-            title: title,
-            tags: tags,
-            featured: featured,
-            keypoints: keypoints,
-            summary: summary,
-            text_fields: Vec::new(),
-            last_modified: last_modified,
-        }
+impl Nested for Keypoint {
+    const NAME: &'static str = "keypoint";
+    const PATH: &'static str = "keypoints";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u32) {
+        self.id = id;
     }
+
+    fn items(collection: &mut Collection) -> &mut Vec<Self> {
+        &mut collection.keypoints
+    }
+
+    fn position(&self) -> u32 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: u32) {
+        self.position = position;
+    }
+}
+
+impl Nested for TextField {
+    const NAME: &'static str = "text field";
+    const PATH: &'static str = "text-fields";
+
+    fn id(&self) -> u32 {
+        self.id
+    }
+
+    fn set_id(&mut self, id: u32) {
+        self.id = id;
+    }
+
+    fn items(collection: &mut Collection) -> &mut Vec<Self> {
+        &mut collection.text_fields
+    }
+}
+
+/// Namespace for the UUIDs derived from the `u32` ids used by older `projects.json` files.
+const LEGACY_ID_NAMESPACE: Uuid = Uuid::from_u128(0x607e3629_5fc5_405b_9b60_a7b4d8adfb85);
+
+impl Collection {
     pub fn default(projects_data: Vec<Collection>) -> Self {
-        let id: u32 = projects_data.len().try_into().unwrap_or(0);
+        let number = projects_data.len();
+        let position = next_position(&projects_data);
         let keypoint = Keypoint {
             id: 0,
-            featured: vec!["n/a".to_string()],
-            title: format!("New Keypoint 1 - {}", id),
-            summary: format!("New Summary 1 - {}", id),
+            position: 0,
+            featured: vec![PLACEHOLDER.to_string()],
+            title: format!("New Keypoint 1 - {}", number),
+            summary: format!("New Summary 1 - {}", number),
         };
         Collection {
-            id: id,
-            client: format!("New Client {}", id),
-            client_logo: "n/a".to_string(),
+            id: Uuid::new_v4(),
+            client: format!("New Client {}", number),
+            client_logo: PLACEHOLDER.to_string(),
             accent_color: "#cacaca".to_string(),
-            title: format!("New Title {}", id),
+            title: format!("New Title {}", number),
             tags: vec!["Default".to_string()],
-            featured: "n/a".to_string(),
+            featured: PLACEHOLDER.to_string(),
             keypoints: vec![keypoint],
-            summary: format!("New Summary {}", id),
+            summary: format!("New Summary {}", number),
             text_fields: Vec::new(),
-            last_modified: Local::now().format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            position,
+            last_modified: timestamp(),
+            revision: 1,
+            status: Status::default(),
+            publish_at: None,
+        }
+    }
+
+    pub fn is_published(&self) -> bool {
+        self.status == Status::Published
+    }
+
+    /// Sorts keypoints by position and renumbers them from zero.
+    pub fn order_keypoints(&mut self) {
+        self.keypoints.sort_by_key(|keypoint| keypoint.position);
+        for (keypoint, position) in self.keypoints.iter_mut().zip(0..) {
+            keypoint.position = position;
         }
     }
 }
 
+/// The position that puts a new collection after all of `collections`.
+pub fn next_position(collections: &[Collection]) -> u32 {
+    collections
+        .iter()
+        .map(|collection| collection.position.saturating_add(1))
+        .max()
+        .unwrap_or(0)
+}
+
 pub fn load_from_storage(local_projects_path: &str) -> Result<Vec<Collection>> {
     match File::open(local_projects_path) {
         Ok(local_projects_file) => {
@@ -107,20 +203,23 @@ pub fn load_from_storage(local_projects_path: &str) -> Result<Vec<Collection>> {
             match reader.read_to_end(&mut buffer) {
                 Ok(size) => {
                     println!("Local projects data size: {}", size);
-                    match serde_json::from_slice::<Vec<Collection>>(&buffer) {
-                        Ok(local_projects_data) => {
+                    match parse_collections(&buffer) {
+                        Ok((local_projects_data, false)) => {
                             println!("Successfully loaded local projects data.");
-                            return Ok(local_projects_data);
+                            Ok(local_projects_data)
+                        }
+                        Ok((local_projects_data, true)) => {
+                            migrate_local_db(local_projects_path, local_projects_data)
                         }
                         Err(error) => {
                             eprintln!("Local projects data structure is incorrect: {}", error);
-                            return Err(error.into());
+                            Err(error.into())
                         }
                     }
                 }
                 Err(error) => {
                     eprintln!("Local projects data could not be read: {}", error);
-                    return Err(error);
+                    Err(error)
                 }
             }
         }
@@ -137,8 +236,8 @@ pub async fn load_from_cdn(remote_projects_path: &str) -> Result<Vec<Collection>
         Ok(mut response) => match response.body().await {
             Ok(body) => {
                 println!("Remote projects data size: {}", body.len());
-                match serde_json::from_slice::<Vec<Collection>>(&body) {
-                    Ok(projects_data) => {
+                match parse_collections(&body) {
+                    Ok((projects_data, _)) => {
                         println!("Remote projects data loaded!");
                         Ok(projects_data)
                     }
@@ -166,15 +265,100 @@ pub async fn load_from_cdn(remote_projects_path: &str) -> Result<Vec<Collection>
 }
 
 pub fn write_local_db(path: &str, projects: Vec<Collection>) -> Result<Vec<Collection>> {
-    match File::create(&path) {
-        Ok(file) => {
-            let writer = BufWriter::new(file);
-            let _ = serde_json::to_writer_pretty(writer, &projects);
-            Ok(projects)
+    match write_json_atomic(path, &projects) {
+        Ok(_) => Ok(projects),
+        Err(error) => {
+            eprintln!("Could not write local projects database: {}", error);
+            Err(error)
+        }
+    }
+}
+
+/// Parses a projects file, upgrading any legacy `u32` collection ids to UUIDs.
+/// The flag is set when at least one id had to be upgraded. Collections stored
+/// before statuses existed were all public, so they load as published.
+pub fn parse_collections(buffer: &[u8]) -> serde_json::Result<(Vec<Collection>, bool)> {
+    let mut values = serde_json::from_slice::<Vec<Value>>(buffer)?;
+    let mut migrated = false;
+    for value in values.iter_mut() {
+        if let Some(id) = value.get("id").and_then(Value::as_u64) {
+            value["id"] = Value::String(legacy_id(id).to_string());
+            migrated = true;
+        }
+        upgrade_status(value);
+    }
+    let collections = values
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<serde_json::Result<Vec<Collection>>>()?;
+    Ok((collections, migrated))
+}
+
+/// Marks a stored collection without a `status` as published, since every
+/// collection stored before statuses existed was public.
+pub fn upgrade_status(collection: &mut Value) {
+    if let Some(fields) = collection.as_object_mut() {
+        fields
+            .entry("status")
+            .or_insert_with(|| Value::String("published".to_string()));
+    }
+}
+
+/// Maps a legacy index-based id to a UUID. The mapping is deterministic so the local
+/// file, its backup and the CDN copy all upgrade to the same ids.
+pub fn legacy_id(id: u64) -> Uuid {
+    Uuid::new_v5(&LEGACY_ID_NAMESPACE, id.to_string().as_bytes())
+}
+
+fn migrate_local_db(path: &str, projects: Vec<Collection>) -> Result<Vec<Collection>> {
+    let legacy_path = format!("{}.legacy", path);
+    println!("Upgrading legacy collection ids in \"{}\"...", path);
+    match fs::copy(path, &legacy_path) {
+        Ok(_) => {
+            println!("Original file kept at \"{}\"", legacy_path);
+            write_local_db(path, projects)
         }
         Err(error) => {
-            eprintln!("Could not create local projects database: {}", error);
+            eprintln!(
+                "Could not keep a copy of the legacy projects file: {}",
+                error
+            );
             Err(error)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::{legacy_id, load_from_storage, write_local_db, Collection};
+    use crate::core::utils::TempDir;
+
+    #[test]
+    fn upgrades_legacy_ids_once() {
+        let dir = TempDir::create();
+        let path = &dir.path("projects.json");
+        let mut legacy = json!(Collection::default(Vec::new()));
+        legacy["id"] = json!(3);
+        let original = serde_json::to_vec(&json!([legacy])).unwrap();
+        fs::write(path, &original).unwrap();
+
+        let loaded = load_from_storage(path).unwrap();
+        assert_eq!(loaded[0].id, legacy_id(3));
+        assert_eq!(legacy_id(3), legacy_id(3));
+        assert_ne!(legacy_id(3), legacy_id(4));
+        assert_eq!(fs::read(format!("{}.legacy", path)).unwrap(), original);
+        assert_eq!(load_from_storage(path).unwrap(), loaded);
+
+        // Files that already use UUIDs are left exactly as they are.
+        let current = &dir.path("current.json");
+        write_local_db(current, loaded.clone()).unwrap();
+        let written = fs::read(current).unwrap();
+        assert_eq!(load_from_storage(current).unwrap(), loaded);
+        assert_eq!(fs::read(current).unwrap(), written);
+        assert!(!dir.root().join("current.json.legacy").exists());
+    }
+}
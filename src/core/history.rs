@@ -0,0 +1,135 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Error, ErrorKind, Result, Write},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::core::{data::Collection, patch::diff, settings::Settings, utils::timestamp};
+
+/// What a mutation did to a collection.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+    Revert,
+    Reorder,
+    Retag,
+    Restore,
+    Publish,
+}
+
+/// One recorded change to a collection.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Revision {
+    pub collection: Uuid,
+    /// The collection's `revision` after the change.
+    pub revision: u64,
+    pub action: Action,
+    /// Name of the API key whose session made the change.
+    pub author: String,
+    pub timestamp: String,
+    /// JSON Patch from the previous version to this one.
+    pub diff: Vec<Value>,
+    /// The collection as it was after the change; for deletions, as it was
+    /// when deleted. Omitted from history listings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Collection>,
+}
+
+impl Revision {
+    /// Describes the change from `before` to `after`; either may be `None` for
+    /// creations and deletions.
+    pub fn new(
+        action: Action,
+        author: &str,
+        before: Option<&Collection>,
+        after: Option<&Collection>,
+    ) -> Result<Self> {
+        let Some(current) = after.or(before) else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "A revision needs a collection before or after the change.",
+            ));
+        };
+        let before_value = serde_json::to_value(before)?;
+        let after_value = serde_json::to_value(after)?;
+        Ok(Revision {
+            collection: current.id,
+            revision: current.revision,
+            action,
+            author: author.to_string(),
+            timestamp: timestamp(),
+            diff: diff(&before_value, &after_value),
+            snapshot: Some(current.clone()),
+        })
+    }
+}
+
+/// Append-only log of every revision, one JSON object per line.
+pub struct History {
+    path: String,
+}
+
+impl History {
+    pub fn new(path: &str) -> Self {
+        History {
+            path: path.to_string(),
+        }
+    }
+
+    /// The history log kept next to the projects file.
+    pub fn open(settings: &Settings) -> Self {
+        History::new(&format!(
+            "{}/{}.history.jsonl",
+            settings.local_projects_path.value, settings.projects_file_name.value
+        ))
+    }
+
+    pub fn record(&self, revision: &Revision) -> Result<()> {
+        if let Some(dir) = Path::new(&self.path).parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(revision)?;
+        line.push(b'\n');
+        // One write per entry so concurrent appends cannot interleave within a line.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+
+    /// Every revision of one collection, oldest first.
+    pub fn list(&self, id: Uuid) -> Result<Vec<Revision>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut revisions = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Revision>(&line) {
+                Ok(revision) if revision.collection == id => revisions.push(revision),
+                Ok(_) => {}
+                // A torn final line from a crash should not hide the rest of the history.
+                Err(error) => eprintln!(
+                    "Skipping unreadable history entry on line {}: {}",
+                    number + 1,
+                    error
+                ),
+            }
+        }
+        Ok(revisions)
+    }
+}
@@ -1,3 +1,12 @@
 pub mod settings;
+pub mod backup;
 pub mod data;
-pub mod utils;
\ No newline at end of file
+pub mod history;
+pub mod patch;
+pub mod query;
+pub mod search;
+pub mod store;
+pub mod tags;
+pub mod trash;
+pub mod utils;
+pub mod validation;
@@ -0,0 +1,347 @@
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+/// Why a patch could not be applied.
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    /// The patch document itself is malformed.
+    Invalid(String),
+    /// The patch is well formed but does not apply to the current document,
+    /// e.g. a path does not exist or a `test` operation failed.
+    Conflict(String),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::Invalid(message) | PatchError::Conflict(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+/// Applies an RFC 7396 JSON Merge Patch: objects are merged recursively, `null`
+/// removes a member and any other value replaces the target outright.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Applies an RFC 6902 JSON Patch. Operations are applied in order to a copy,
+/// so `document` is only changed if every operation succeeds.
+pub fn json_patch(document: &mut Value, operations: &Value) -> Result<(), PatchError> {
+    let Value::Array(operations) = operations else {
+        return Err(invalid(
+            "A JSON Patch document must be an array of operations.",
+        ));
+    };
+    let mut patched = document.clone();
+    for (index, operation) in operations.iter().enumerate() {
+        apply_operation(&mut patched, operation).map_err(|error| match error {
+            PatchError::Invalid(message) => {
+                PatchError::Invalid(format!("Operation {}: {}", index, message))
+            }
+            PatchError::Conflict(message) => {
+                PatchError::Conflict(format!("Operation {}: {}", index, message))
+            }
+        })?;
+    }
+    *document = patched;
+    Ok(())
+}
+
+/// JSON Patch operations that turn `from` into `to`. Objects are compared member
+/// by member and arrays of equal length item by item; anything else that differs
+/// is replaced whole.
+pub fn diff(from: &Value, to: &Value) -> Vec<Value> {
+    let mut operations = Vec::new();
+    diff_at(&mut Vec::new(), from, to, &mut operations);
+    operations
+}
+
+fn diff_at(path: &mut Vec<String>, from: &Value, to: &Value, operations: &mut Vec<Value>) {
+    match (from, to) {
+        _ if from == to => {}
+        (Value::Object(from), Value::Object(to)) => {
+            for (key, value) in from {
+                path.push(key.clone());
+                match to.get(key) {
+                    Some(new) => diff_at(path, value, new, operations),
+                    None => {
+                        operations.push(json!({ "op": "remove", "path": format_pointer(path) }))
+                    }
+                }
+                path.pop();
+            }
+            for (key, value) in to {
+                if !from.contains_key(key) {
+                    path.push(key.clone());
+                    operations
+                        .push(json!({ "op": "add", "path": format_pointer(path), "value": value }));
+                    path.pop();
+                }
+            }
+        }
+        (Value::Array(from), Value::Array(to)) if from.len() == to.len() => {
+            for (index, (old, new)) in from.iter().zip(to).enumerate() {
+                path.push(index.to_string());
+                diff_at(path, old, new, operations);
+                path.pop();
+            }
+        }
+        _ => operations.push(json!({ "op": "replace", "path": format_pointer(path), "value": to })),
+    }
+}
+
+fn apply_operation(document: &mut Value, operation: &Value) -> Result<(), PatchError> {
+    let op = member(operation, "op")?;
+    let path = pointer(member(operation, "path")?)?;
+    match op {
+        "add" => add(document, &path, value(operation)?.clone()),
+        "remove" => remove(document, &path).map(|_| ()),
+        "replace" => {
+            let value = value(operation)?.clone();
+            *resolve(document, &path)? = value;
+            Ok(())
+        }
+        "move" => {
+            let from = pointer(member(operation, "from")?)?;
+            if path.len() > from.len() && path[..from.len()] == from[..] {
+                return Err(invalid("Cannot move a value into one of its children."));
+            }
+            let value = remove(document, &from)?;
+            add(document, &path, value)
+        }
+        "copy" => {
+            let from = pointer(member(operation, "from")?)?;
+            let value = resolve(document, &from)?.clone();
+            add(document, &path, value)
+        }
+        "test" => {
+            if *resolve(document, &path)? == *value(operation)? {
+                Ok(())
+            } else {
+                Err(PatchError::Conflict(format!(
+                    "Test failed at \"{}\".",
+                    format_pointer(&path)
+                )))
+            }
+        }
+        _ => Err(invalid(&format!("Unknown operation \"{}\".", op))),
+    }
+}
+
+fn member<'a>(operation: &'a Value, name: &str) -> Result<&'a str, PatchError> {
+    operation
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid(&format!("Missing string member \"{}\".", name)))
+}
+
+fn value(operation: &Value) -> Result<&Value, PatchError> {
+    operation
+        .get("value")
+        .ok_or_else(|| invalid("Missing member \"value\"."))
+}
+
+/// Splits an RFC 6901 JSON Pointer into unescaped reference tokens.
+fn pointer(path: &str) -> Result<Vec<String>, PatchError> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let Some(path) = path.strip_prefix('/') else {
+        return Err(invalid(&format!(
+            "\"{}\" is not a JSON Pointer; it must start with /.",
+            path
+        )));
+    };
+    path.split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                if c != '~' {
+                    unescaped.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return Err(invalid(&format!("Invalid escape in \"{}\".", token))),
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
+fn format_pointer(path: &[String]) -> String {
+    path.iter()
+        .map(|token| format!("/{}", token.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+fn resolve<'a>(document: &'a mut Value, path: &[String]) -> Result<&'a mut Value, PatchError> {
+    let mut current = document;
+    for token in path {
+        current = match current {
+            Value::Object(map) => map.get_mut(token),
+            Value::Array(items) => match array_index(token, items.len())? {
+                Some(index) => items.get_mut(index),
+                None => None,
+            },
+            _ => None,
+        }
+        .ok_or_else(|| missing(path))?;
+    }
+    Ok(current)
+}
+
+fn add(document: &mut Value, path: &[String], value: Value) -> Result<(), PatchError> {
+    let Some((last, parent)) = path.split_last() else {
+        *document = value;
+        return Ok(());
+    };
+    match resolve(document, parent)? {
+        Value::Object(map) => {
+            map.insert(last.clone(), value);
+            Ok(())
+        }
+        Value::Array(items) => {
+            let index = if last == "-" {
+                items.len()
+            } else {
+                array_index(last, items.len() + 1)?.ok_or_else(|| missing(path))?
+            };
+            items.insert(index, value);
+            Ok(())
+        }
+        _ => Err(missing(path)),
+    }
+}
+
+fn remove(document: &mut Value, path: &[String]) -> Result<Value, PatchError> {
+    let Some((last, parent)) = path.split_last() else {
+        return Err(invalid("Cannot remove the whole document."));
+    };
+    match resolve(document, parent)? {
+        Value::Object(map) => map.remove(last).ok_or_else(|| missing(path)),
+        Value::Array(items) => {
+            let index = array_index(last, items.len())?.ok_or_else(|| missing(path))?;
+            Ok(items.remove(index))
+        }
+        _ => Err(missing(path)),
+    }
+}
+
+/// Parses an array index token. Returns `None` for indexes at or past `len`.
+fn array_index(token: &str, len: usize) -> Result<Option<usize>, PatchError> {
+    let well_formed = !token.is_empty()
+        && token.chars().all(|c| c.is_ascii_digit())
+        && (token == "0" || !token.starts_with('0'));
+    if !well_formed {
+        return Err(invalid(&format!("\"{}\" is not an array index.", token)));
+    }
+    Ok(token.parse::<usize>().ok().filter(|index| *index < len))
+}
+
+fn invalid(message: &str) -> PatchError {
+    PatchError::Invalid(message.to_string())
+}
+
+fn missing(path: &[String]) -> PatchError {
+    PatchError::Conflict(format!("No value at \"{}\".", format_pointer(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{diff, json_patch, merge_patch, PatchError};
+
+    #[test]
+    fn merges_recursively_and_removes_nulls() {
+        let mut target = json!({ "title": "a", "meta": { "x": 1, "y": 2 }, "tags": ["a"] });
+        merge_patch(
+            &mut target,
+            &json!({ "meta": { "x": null, "z": 3 }, "tags": ["b"], "summary": "s" }),
+        );
+        assert_eq!(
+            target,
+            json!({ "title": "a", "meta": { "y": 2, "z": 3 }, "tags": ["b"], "summary": "s" })
+        );
+    }
+
+    #[test]
+    fn diffs_apply_back() {
+        let from = json!({ "title": "a", "tags": ["x"], "keypoints": [{ "id": 0, "a/b": 1 }] });
+        let to = json!({ "title": "b", "tags": ["x", "y"], "keypoints": [{ "id": 0 }], "new": 1 });
+        let operations = diff(&from, &to);
+        assert_eq!(operations.len(), 4);
+        let mut patched = from.clone();
+        json_patch(&mut patched, &Value::Array(operations)).unwrap();
+        assert_eq!(patched, to);
+        assert!(diff(&to, &to).is_empty());
+    }
+
+    #[test]
+    fn applies_operations_atomically() {
+        let original = json!({ "tags": ["a", "b"], "keypoints": [{ "title": "k" }], "a/b": 1 });
+        let mut document = original.clone();
+        json_patch(
+            &mut document,
+            &json!([
+                { "op": "test", "path": "/keypoints/0/title", "value": "k" },
+                { "op": "add", "path": "/tags/-", "value": "c" },
+                { "op": "move", "from": "/tags/0", "path": "/tags/1" },
+                { "op": "replace", "path": "/a~1b", "value": 2 },
+                { "op": "copy", "from": "/keypoints/0", "path": "/keypoints/1" },
+                { "op": "remove", "path": "/keypoints/0" }
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            document,
+            json!({ "tags": ["b", "a", "c"], "keypoints": [{ "title": "k" }], "a/b": 2 })
+        );
+
+        let mut document = original.clone();
+        let error = json_patch(
+            &mut document,
+            &json!([
+                { "op": "add", "path": "/tags/-", "value": "c" },
+                { "op": "remove", "path": "/tags/5" }
+            ]),
+        )
+        .unwrap_err();
+        assert!(matches!(error, PatchError::Conflict(_)));
+        assert_eq!(document, original);
+
+        for operations in [
+            json!({ "op": "add" }),
+            json!([{ "op": "frobnicate", "path": "/tags" }]),
+            json!([{ "op": "add", "path": "tags", "value": 1 }]),
+            json!([{ "op": "remove", "path": "/tags/01" }]),
+            json!([{ "op": "replace", "path": "/tags/0" }]),
+        ] {
+            let error = json_patch(&mut document, &operations).unwrap_err();
+            assert!(matches!(error, PatchError::Invalid(_)), "{:?}", operations);
+        }
+    }
+}
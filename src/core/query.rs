@@ -0,0 +1,237 @@
+use std::{
+    cmp::Ordering,
+    io::{Error, ErrorKind, Result},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Deserialize;
+
+use crate::core::{
+    data::{Collection, Status, PLACEHOLDER},
+    tags::normalize_tag,
+    utils::parse_timestamp,
+};
+
+/// Largest page `limit` a client may ask for.
+pub const MAX_LIMIT: usize = 100;
+
+/// Query parameters accepted by `GET /v1/projects`. Without any, every
+/// collection is returned in display order.
+#[derive(Deserialize, Default, Debug)]
+pub struct ListQuery {
+    /// Only collections carrying this tag, compared in normalized form.
+    pub tag: Option<String>,
+    /// Only collections for this client, compared case-insensitively.
+    pub client: Option<String>,
+    /// `true` for collections with a featured image, `false` for those without.
+    pub featured: Option<bool>,
+    /// Only collections in this publishing state.
+    pub status: Option<Status>,
+    /// Only collections changed at or after this time.
+    pub modified_since: Option<String>,
+    /// `title`, `last_modified` or `position` (the default); prefix with `-` to reverse.
+    pub sort: Option<String>,
+    pub limit: Option<usize>,
+    /// Opaque value from a previous page's `next_cursor`.
+    pub cursor: Option<String>,
+}
+
+/// One page of a filtered listing.
+pub struct Page {
+    pub items: Vec<Collection>,
+    /// Number of collections matching the filters, across all pages.
+    pub total: usize,
+    /// Cursor for the following page, if there is one.
+    pub next_cursor: Option<String>,
+}
+
+impl ListQuery {
+    /// Filters, sorts and pages `collections`, which must be in display order.
+    /// Fails with `ErrorKind::InvalidInput` for parameters that cannot be used.
+    pub fn apply(&self, collections: &[Collection]) -> Result<Page> {
+        let modified_since = match &self.modified_since {
+            Some(value) => Some(parse_timestamp(value).ok_or_else(|| {
+                invalid(format!("modified_since \"{}\" is not a valid time", value))
+            })?),
+            None => None,
+        };
+        let tag = self.tag.as_deref().map(normalize_tag);
+        let offset = match &self.cursor {
+            Some(cursor) => decode_cursor(cursor)?,
+            None => 0,
+        };
+        let limit = match self.limit {
+            Some(limit) if limit == 0 || limit > MAX_LIMIT => {
+                return Err(invalid(format!(
+                    "limit must be between 1 and {}",
+                    MAX_LIMIT
+                )))
+            }
+            Some(limit) => limit,
+            None => usize::MAX,
+        };
+        let (field, descending) = match self.sort.as_deref() {
+            Some(sort) => match sort.strip_prefix('-') {
+                Some(field) => (field, true),
+                None => (sort, false),
+            },
+            None => ("position", false),
+        };
+        let compare: fn(&Collection, &Collection) -> Ordering = match field {
+            "position" => |a, b| a.position.cmp(&b.position),
+            "title" => |a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()),
+            "last_modified" => {
+                |a, b| parse_timestamp(&a.last_modified).cmp(&parse_timestamp(&b.last_modified))
+            }
+            _ => {
+                return Err(invalid(format!(
+                    "Cannot sort by \"{}\"; use title, last_modified or position",
+                    field
+                )))
+            }
+        };
+
+        let mut matching: Vec<&Collection> = collections
+            .iter()
+            .filter(|collection| {
+                tag.as_ref().is_none_or(|tag| {
+                    collection
+                        .tags
+                        .iter()
+                        .any(|item| normalize_tag(item) == *tag)
+                })
+            })
+            .filter(|collection| {
+                self.client
+                    .as_ref()
+                    .is_none_or(|client| collection.client.eq_ignore_ascii_case(client))
+            })
+            .filter(|collection| {
+                self.featured.is_none_or(|featured| {
+                    let has_featured =
+                        !collection.featured.is_empty() && collection.featured != PLACEHOLDER;
+                    has_featured == featured
+                })
+            })
+            .filter(|collection| self.status.is_none_or(|status| collection.status == status))
+            .filter(|collection| {
+                modified_since.is_none_or(|since| {
+                    parse_timestamp(&collection.last_modified).is_some_and(|time| time >= since)
+                })
+            })
+            .collect();
+        // Stable sorts keep display order among equal keys.
+        if descending {
+            matching.sort_by(|a, b| compare(b, a));
+        } else {
+            matching.sort_by(|a, b| compare(a, b));
+        }
+
+        let total = matching.len();
+        let items: Vec<Collection> = matching
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect();
+        let end = offset.saturating_add(items.len());
+        Ok(Page {
+            items,
+            total,
+            next_cursor: (end < total).then(|| URL_SAFE_NO_PAD.encode(end.to_string())),
+        })
+    }
+}
+
+fn decode_cursor(cursor: &str) -> Result<usize> {
+    URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|offset| offset.parse().ok())
+        .ok_or_else(|| invalid(format!("\"{}\" is not a valid cursor", cursor)))
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ListQuery;
+    use crate::core::data::Collection;
+
+    fn collections() -> Vec<Collection> {
+        let mut collections = Vec::new();
+        for (title, tag, modified) in [
+            ("Beta", "UI", "2024-01-02 00:00:00 UTC"),
+            ("alpha", "ui", "2024-03-01 00:00:00 UTC"),
+            ("Gamma", "Print", "2024-02-01 00:00:00 UTC"),
+        ] {
+            let mut collection = Collection::default(collections.clone());
+            collection.title = title.to_string();
+            collection.tags = vec![tag.to_string()];
+            collection.last_modified = modified.to_string();
+            collections.push(collection);
+        }
+        collections
+    }
+
+    fn titles(query: &ListQuery) -> Vec<String> {
+        let page = query.apply(&collections()).unwrap();
+        page.items.into_iter().map(|item| item.title).collect()
+    }
+
+    #[test]
+    fn filters_and_sorts() {
+        let query = ListQuery {
+            tag: Some("ui".to_string()),
+            sort: Some("title".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(titles(&query), ["alpha", "Beta"]);
+
+        let query = ListQuery {
+            modified_since: Some("2024-01-15".to_string()),
+            sort: Some("-last_modified".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(titles(&query), ["alpha", "Gamma"]);
+    }
+
+    #[test]
+    fn pages_with_cursors() {
+        let mut query = ListQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+        let page = query.apply(&collections()).unwrap();
+        assert_eq!((page.items.len(), page.total), (2, 3));
+
+        query.cursor = page.next_cursor;
+        let page = query.apply(&collections()).unwrap();
+        assert_eq!(page.items[0].title, "Gamma");
+        assert!(page.next_cursor.is_none());
+
+        for query in [
+            ListQuery {
+                limit: Some(0),
+                ..Default::default()
+            },
+            ListQuery {
+                cursor: Some("not a cursor".to_string()),
+                ..Default::default()
+            },
+            ListQuery {
+                sort: Some("client".to_string()),
+                ..Default::default()
+            },
+            ListQuery {
+                modified_since: Some("yesterday".to_string()),
+                ..Default::default()
+            },
+        ] {
+            assert!(query.apply(&collections()).is_err());
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::core::data::Collection;
+
+/// Characters of context kept on each side of the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// One searchable piece of text from a collection.
+struct Field {
+    /// Index of the collection in `SearchIndex::collections`.
+    collection: usize,
+    /// Path to the field, e.g. `keypoints[2].summary`.
+    path: String,
+    text: String,
+    weight: u32,
+}
+
+/// A collection matching a search, with the field that matched best.
+#[derive(Serialize, Debug)]
+pub struct Hit {
+    pub id: Uuid,
+    pub title: String,
+    pub score: u32,
+    pub field: String,
+    pub snippet: String,
+}
+
+/// In-memory inverted index over the text of every collection. Cheap enough to
+/// rebuild from scratch whenever the collections change.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// Id, title and whether the collection is published.
+    collections: Vec<(Uuid, String, bool)>,
+    fields: Vec<Field>,
+    /// Lowercased word to the fields containing it.
+    postings: BTreeMap<String, Vec<usize>>,
+}
+
+impl SearchIndex {
+    pub fn build(collections: &[Collection]) -> Self {
+        let mut index = SearchIndex::default();
+        for (position, collection) in collections.iter().enumerate() {
+            index.collections.push((
+                collection.id,
+                collection.title.clone(),
+                collection.is_published(),
+            ));
+            index.add(position, "title", &collection.title, 5);
+            for (tag_index, tag) in collection.tags.iter().enumerate() {
+                index.add(position, &format!("tags[{}]", tag_index), tag, 4);
+            }
+            index.add(position, "client", &collection.client, 3);
+            index.add(position, "summary", &collection.summary, 2);
+            for (keypoint_index, keypoint) in collection.keypoints.iter().enumerate() {
+                let path = format!("keypoints[{}]", keypoint_index);
+                index.add(position, &format!("{}.title", path), &keypoint.title, 2);
+                index.add(position, &format!("{}.summary", path), &keypoint.summary, 1);
+            }
+            for (field_index, text_field) in collection.text_fields.iter().enumerate() {
+                let path = format!("text_fields[{}].value", field_index);
+                index.add(position, &path, &text_field.value, 1);
+            }
+        }
+        index
+    }
+
+    fn add(&mut self, collection: usize, path: &str, text: &str, weight: u32) {
+        let field = self.fields.len();
+        let mut seen = false;
+        for (_, word) in words(text) {
+            let postings = self.postings.entry(word).or_default();
+            if postings.last() != Some(&field) {
+                postings.push(field);
+            }
+            seen = true;
+        }
+        if seen {
+            self.fields.push(Field {
+                collection,
+                path: path.to_string(),
+                text: text.to_string(),
+                weight,
+            });
+        }
+    }
+
+    /// Collections containing every word of `query`, best first. Words match
+    /// whole indexed words or their beginnings; whole-word matches score double.
+    /// With `published_only`, drafts and other unpublished collections are skipped.
+    pub fn search(&self, query: &str, limit: usize, published_only: bool) -> Vec<Hit> {
+        let terms: Vec<String> = words(query).map(|(_, word)| word).collect();
+        if terms.is_empty() {
+            return Vec::new();
+        }
+        // Per collection: total score, terms matched, and the best field with its score.
+        let mut matches: HashMap<usize, (u32, Vec<bool>, usize, u32)> = HashMap::new();
+        for (term_index, term) in terms.iter().enumerate() {
+            let candidates = self
+                .postings
+                .range(term.clone()..)
+                .take_while(|(word, _)| word.starts_with(term.as_str()));
+            for (word, fields) in candidates {
+                let multiplier = if word == term { 2 } else { 1 };
+                for &field in fields {
+                    let Field {
+                        collection, weight, ..
+                    } = self.fields[field];
+                    let score = weight * multiplier;
+                    let entry = matches
+                        .entry(collection)
+                        .or_insert_with(|| (0, vec![false; terms.len()], field, 0));
+                    entry.0 = entry.0.saturating_add(score);
+                    entry.1[term_index] = true;
+                    if score > entry.3 {
+                        entry.2 = field;
+                        entry.3 = score;
+                    }
+                }
+            }
+        }
+
+        let mut hits: Vec<(usize, u32, usize)> = matches
+            .into_iter()
+            .filter(|(_, (_, matched, _, _))| matched.iter().all(|matched| *matched))
+            .filter(|(collection, _)| !published_only || self.collections[*collection].2)
+            .map(|(collection, (score, _, field, _))| (collection, score, field))
+            .collect();
+        hits.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hits.into_iter()
+            .take(limit)
+            .map(|(collection, score, field)| {
+                let (id, title, _) = &self.collections[collection];
+                let field = &self.fields[field];
+                Hit {
+                    id: *id,
+                    title: title.clone(),
+                    score,
+                    field: field.path.clone(),
+                    snippet: snippet(&field.text, &terms),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Lowercased alphanumeric words of `text`, each with the char index it starts at.
+fn words(text: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut chars = text.chars().enumerate().peekable();
+    std::iter::from_fn(move || {
+        while chars.next_if(|(_, c)| !c.is_alphanumeric()).is_some() {}
+        let (start, first) = chars.next()?;
+        let mut word: String = first.to_lowercase().collect();
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_alphanumeric()) {
+            word.extend(c.to_lowercase());
+        }
+        Some((start, word))
+    })
+}
+
+/// A short excerpt of `text` around the first word matching one of `terms`.
+fn snippet(text: &str, terms: &[String]) -> String {
+    let start = words(text)
+        .find(|(_, word)| terms.iter().any(|term| word.starts_with(term.as_str())))
+        .map_or(0, |(start, _)| start);
+    let length = text.chars().count();
+    let from = start.saturating_sub(SNIPPET_CONTEXT);
+    let to = length.min(start + SNIPPET_CONTEXT * 2);
+    let mut snippet: String = text.chars().skip(from).take(to - from).collect();
+    snippet = snippet.trim().to_string();
+    if from > 0 {
+        snippet.insert(0, '…');
+    }
+    if to < length {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchIndex;
+    use crate::core::data::{Collection, Status};
+
+    #[test]
+    fn ranks_hits_by_field_weight() {
+        let mut first = Collection::default(Vec::new());
+        first.title = "Annual report".to_string();
+        first.summary = "A brand refresh for a coffee roaster.".to_string();
+        first.status = Status::Published;
+        let mut second = Collection::default(Vec::new());
+        second.title = "Coffee brand identity".to_string();
+        second.keypoints[0].summary = "Typography and colour work".to_string();
+        let index = SearchIndex::build(&[first, second]);
+
+        let hits = index.search("Coffee bran", 10, false);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].title, "Coffee brand identity");
+        assert_eq!(hits[0].field, "title");
+        assert_eq!(hits[1].field, "summary");
+
+        let hits = index.search("typography", 10, false);
+        assert_eq!(hits[0].field, "keypoints[0].summary");
+        assert_eq!(hits[0].snippet, "Typography and colour work");
+
+        assert!(index.search("typography", 10, true).is_empty());
+        assert_eq!(index.search("coffee", 10, true)[0].title, "Annual report");
+
+        assert!(index.search("coffee missing", 10, false).is_empty());
+        assert!(index.search("  ", 10, false).is_empty());
+    }
+}
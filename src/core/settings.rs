@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::{
     fs::File,
     io::{BufReader, Error, Read, Write},
-    net::{Ipv4Addr, SocketAddr},
+    net::Ipv4Addr,
 };
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -13,6 +13,22 @@ pub struct Settings {
     pub local_projects_path: StrSetting,
     pub local_backup_path: StrSetting,
     pub projects_file_name: StrSetting,
+    #[serde(default = "default_storage_backend")]
+    pub storage_backend: StrSetting,
+    #[serde(default = "default_session_ttl")]
+    pub session_ttl_minutes: U16Setting,
+    #[serde(default = "default_trash_retention")]
+    pub trash_retention_days: U16Setting,
+    #[serde(default = "default_backup_interval")]
+    pub backup_interval_minutes: U16Setting,
+    #[serde(default = "default_backup_keep_hourly")]
+    pub backup_keep_hourly: U16Setting,
+    #[serde(default = "default_backup_keep_daily")]
+    pub backup_keep_daily: U16Setting,
+    #[serde(default = "default_backup_keep_weekly")]
+    pub backup_keep_weekly: U16Setting,
+    #[serde(default = "default_backup_keep_event_days")]
+    pub backup_keep_event_days: U16Setting,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -33,12 +49,6 @@ pub struct Ipv4Setting {
     pub value: Ipv4Addr,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct SocketAddrSetting {
-    pub name: String,
-    pub value: SocketAddr,
-}
-
 impl Settings {
     // TODO: Settings import/export
     pub fn load() -> Result<Self, Error> {
@@ -50,7 +60,7 @@ impl Settings {
                     Ok(_) => match serde_json::from_slice::<Settings>(&buffer) {
                         Ok(settings) => Ok(settings),
                         Err(error) => {
-                            let error: Error = error.try_into().unwrap();
+                            let error = Error::from(error);
                             eprintln!("Failed to parse settings file: {}", error);
                             if let Err(error) = Settings::new() {
                                 fatal_load_error(&error);
@@ -76,6 +86,7 @@ impl Settings {
         }
     }
 
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> Result<(), Error> {
         println!("Creating settings file...");
         match File::create("core/settings.json") {
@@ -126,10 +137,79 @@ impl Settings {
                 name: "Projects File Name".to_string(),
                 value: "projects".to_string(),
             },
+            storage_backend: default_storage_backend(),
+            session_ttl_minutes: default_session_ttl(),
+            trash_retention_days: default_trash_retention(),
+            backup_interval_minutes: default_backup_interval(),
+            backup_keep_hourly: default_backup_keep_hourly(),
+            backup_keep_daily: default_backup_keep_daily(),
+            backup_keep_weekly: default_backup_keep_weekly(),
+            backup_keep_event_days: default_backup_keep_event_days(),
         }
     }
 }
 
+/// Either "json" or "sqlite".
+fn default_storage_backend() -> StrSetting {
+    StrSetting {
+        name: "Storage Backend".to_string(),
+        value: "json".to_string(),
+    }
+}
+
+fn default_session_ttl() -> U16Setting {
+    U16Setting {
+        name: "Session Lifetime (minutes)".to_string(),
+        value: 15,
+    }
+}
+
+/// 0 keeps deleted collections until they are restored.
+fn default_trash_retention() -> U16Setting {
+    U16Setting {
+        name: "Trash Retention (days)".to_string(),
+        value: 30,
+    }
+}
+
+/// 0 turns scheduled backups off; snapshots are still taken at startup and
+/// before destructive changes.
+fn default_backup_interval() -> U16Setting {
+    U16Setting {
+        name: "Backup Interval (minutes)".to_string(),
+        value: 60,
+    }
+}
+
+fn default_backup_keep_hourly() -> U16Setting {
+    U16Setting {
+        name: "Hourly Backups Kept".to_string(),
+        value: 24,
+    }
+}
+
+fn default_backup_keep_daily() -> U16Setting {
+    U16Setting {
+        name: "Daily Backups Kept".to_string(),
+        value: 7,
+    }
+}
+
+fn default_backup_keep_weekly() -> U16Setting {
+    U16Setting {
+        name: "Weekly Backups Kept".to_string(),
+        value: 4,
+    }
+}
+
+/// Days to keep snapshots taken before a change, e.g. a delete or revert.
+fn default_backup_keep_event_days() -> U16Setting {
+    U16Setting {
+        name: "Pre-Change Backups Kept (days)".to_string(),
+        value: 14,
+    }
+}
+
 fn fatal_load_error(error: &Error) {
     eprintln!("Settings load error: {}", error);
     std::process::exit(1);
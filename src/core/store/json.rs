@@ -0,0 +1,101 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    time::SystemTime,
+};
+
+use uuid::Uuid;
+
+use super::{file_changed_at, missing, Store};
+use crate::core::data::{load_from_storage, write_local_db, Collection};
+
+/// Keeps every collection in a single projects JSON file, rewritten on each change.
+pub struct JsonStore {
+    path: String,
+}
+
+impl JsonStore {
+    pub fn new(path: &str) -> Self {
+        JsonStore {
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Store for JsonStore {
+    fn list(&self) -> Result<Vec<Collection>> {
+        load_from_storage(&self.path)
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Collection>> {
+        Ok(self.list()?.into_iter().find(|item| item.id == id))
+    }
+
+    fn insert(&self, collection: Collection) -> Result<Collection> {
+        let mut collections = self.list()?;
+        if collections.iter().any(|item| item.id == collection.id) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("Collection {} already exists", collection.id),
+            ));
+        }
+        collections.push(collection.clone());
+        write_local_db(&self.path, collections)?;
+        Ok(collection)
+    }
+
+    fn update(&self, collection: Collection) -> Result<Option<Collection>> {
+        let mut collections = self.list()?;
+        let Some(item) = collections.iter_mut().find(|item| item.id == collection.id) else {
+            return Ok(None);
+        };
+        *item = collection.clone();
+        write_local_db(&self.path, collections)?;
+        Ok(Some(collection))
+    }
+
+    fn update_all(&self, updated: Vec<Collection>) -> Result<()> {
+        let mut collections = self.list()?;
+        for collection in updated {
+            let Some(item) = collections.iter_mut().find(|item| item.id == collection.id) else {
+                return Err(missing(collection.id));
+            };
+            *item = collection;
+        }
+        collections.sort_by_key(|item| item.position);
+        write_local_db(&self.path, collections).map(|_| ())
+    }
+
+    fn replace_all(&self, mut collections: Vec<Collection>) -> Result<()> {
+        for (index, collection) in collections.iter().enumerate() {
+            if collections[..index]
+                .iter()
+                .any(|item| item.id == collection.id)
+            {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("Collection {} is listed twice", collection.id),
+                ));
+            }
+        }
+        collections.sort_by_key(|item| item.position);
+        write_local_db(&self.path, collections).map(|_| ())
+    }
+
+    fn delete(&self, id: Uuid) -> Result<Option<Collection>> {
+        let mut collections = self.list()?;
+        let Some(index) = collections.iter().position(|item| item.id == id) else {
+            return Ok(None);
+        };
+        let collection = collections.remove(index);
+        write_local_db(&self.path, collections)?;
+        Ok(Some(collection))
+    }
+
+    fn snapshot(&self, path: &str) -> Result<()> {
+        write_local_db(path, self.list()?).map(|_| ())
+    }
+
+    fn changed_at(&self) -> Result<Option<SystemTime>> {
+        file_changed_at(&self.path)
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    fs,
+    io::{Error, ErrorKind, Result},
+    time::SystemTime,
+};
+
+use uuid::Uuid;
+
+use crate::core::{data::Collection, settings::Settings};
+
+pub mod json;
+pub mod sqlite;
+
+pub use json::JsonStore;
+pub use sqlite::SqliteStore;
+
+/// Persistence for the collections served by the API.
+///
+/// Implementations keep collections ordered by `position` and are shared between
+/// server workers, so every method takes `&self`.
+pub trait Store: Send + Sync {
+    /// Every stored collection, ordered by `position`.
+    fn list(&self) -> Result<Vec<Collection>>;
+
+    fn get(&self, id: Uuid) -> Result<Option<Collection>>;
+
+    /// Appends a new collection. Fails with `ErrorKind::AlreadyExists` if its id is taken.
+    fn insert(&self, collection: Collection) -> Result<Collection>;
+
+    /// Replaces the collection with the same id, returning `None` if there is none.
+    fn update(&self, collection: Collection) -> Result<Option<Collection>>;
+
+    /// Replaces several collections in one atomic write. Fails with
+    /// `ErrorKind::NotFound`, changing nothing, if any of them is missing.
+    fn update_all(&self, collections: Vec<Collection>) -> Result<()>;
+
+    /// Replaces every stored collection with `collections` in one atomic write.
+    fn replace_all(&self, collections: Vec<Collection>) -> Result<()>;
+
+    /// Removes a collection, returning it if it existed.
+    fn delete(&self, id: Uuid) -> Result<Option<Collection>>;
+
+    /// Writes a point-in-time copy of every collection to `path` as a projects JSON file.
+    fn snapshot(&self, path: &str) -> Result<()>;
+
+    /// Modification time of the backing file, used to notice outside edits.
+    /// `None` if the file does not exist yet.
+    fn changed_at(&self) -> Result<Option<SystemTime>>;
+}
+
+fn missing(id: Uuid) -> Error {
+    Error::new(ErrorKind::NotFound, format!("No collection with id {}", id))
+}
+
+fn file_changed_at(path: &str) -> Result<Option<SystemTime>> {
+    match fs::metadata(path) {
+        Ok(metadata) => metadata.modified().map(Some),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Opens the backend selected by the `storage_backend` setting.
+pub fn open_store(settings: &Settings) -> Result<Box<dyn Store>> {
+    let base_path = format!(
+        "{}/{}",
+        settings.local_projects_path.value, settings.projects_file_name.value
+    );
+    match settings.storage_backend.value.as_str() {
+        "json" => Ok(Box::new(JsonStore::new(&format!("{}.json", base_path)))),
+        "sqlite" => {
+            let store = SqliteStore::open(&format!("{}.db", base_path))?;
+            store.import_once(&format!("{}.json", base_path))?;
+            Ok(Box::new(store))
+        }
+        backend => {
+            let error = Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown storage backend \"{}\"", backend),
+            );
+            eprintln!("Could not open projects store: {}", error);
+            Err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use uuid::Uuid;
+
+    use super::{JsonStore, SqliteStore, Store};
+    use crate::core::{
+        data::{load_from_storage, write_local_db, Collection},
+        utils::TempDir,
+    };
+
+    fn collection(title: &str, position: u32) -> Collection {
+        let mut collection = Collection::default(Vec::new());
+        collection.id = Uuid::new_v4();
+        collection.title = title.to_string();
+        collection.position = position;
+        collection
+    }
+
+    /// Runs the same operations against a store holding nothing yet.
+    fn exercise(store: &dyn Store, dir: &TempDir) {
+        assert!(store.list().unwrap().is_empty());
+        let first = store.insert(collection("First", 0)).unwrap();
+        let second = store.insert(collection("Second", 1)).unwrap();
+        let error = store.insert(first.clone()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(store.get(second.id).unwrap(), Some(second.clone()));
+        assert_eq!(store.get(Uuid::new_v4()).unwrap(), None);
+
+        let mut renamed = first.clone();
+        renamed.title = "Renamed".to_string();
+        assert_eq!(
+            store.update(renamed.clone()).unwrap(),
+            Some(renamed.clone())
+        );
+        assert_eq!(store.update(collection("Missing", 2)).unwrap(), None);
+
+        // A missing collection leaves the others untouched.
+        let mut moved = second.clone();
+        moved.position = 0;
+        renamed.position = 1;
+        let error = store
+            .update_all(vec![moved.clone(), collection("Missing", 2)])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(store.get(second.id).unwrap(), Some(second.clone()));
+        store
+            .update_all(vec![moved.clone(), renamed.clone()])
+            .unwrap();
+        assert_eq!(store.list().unwrap(), [moved.clone(), renamed.clone()]);
+
+        let path = dir.path("snapshot.json");
+        store.snapshot(&path).unwrap();
+        assert_eq!(
+            load_from_storage(&path).unwrap(),
+            [moved.clone(), renamed.clone()]
+        );
+
+        assert_eq!(store.delete(renamed.id).unwrap(), Some(renamed.clone()));
+        assert_eq!(store.delete(renamed.id).unwrap(), None);
+        assert_eq!(store.list().unwrap(), [moved]);
+    }
+
+    #[test]
+    fn json_and_sqlite_stores_behave_alike() {
+        let dir = TempDir::create();
+        let path = dir.path("projects.json");
+        write_local_db(&path, Vec::new()).unwrap();
+        exercise(&JsonStore::new(&path), &dir);
+
+        let store = SqliteStore::open(&dir.path("projects.db")).unwrap();
+        exercise(&store, &dir);
+    }
+
+    #[test]
+    fn imports_the_json_file_only_once() {
+        let dir = TempDir::create();
+        let json_path = dir.path("projects.json");
+        let db_path = dir.path("projects.db");
+        let imported = collection("Imported", 0);
+        write_local_db(&json_path, vec![imported.clone()]).unwrap();
+
+        let store = SqliteStore::open(&db_path).unwrap();
+        store.import_once(&json_path).unwrap();
+        assert_eq!(store.list().unwrap(), std::slice::from_ref(&imported));
+        store.delete(imported.id).unwrap();
+        drop(store);
+
+        // Emptying the database does not bring the file's collections back.
+        let store = SqliteStore::open(&db_path).unwrap();
+        store.import_once(&json_path).unwrap();
+        assert!(store.list().unwrap().is_empty());
+    }
+}
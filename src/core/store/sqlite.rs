@@ -0,0 +1,271 @@
+use std::{
+    io::{Error, ErrorKind, Result},
+    sync::{Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+use super::{file_changed_at, missing, Store};
+use crate::core::data::{load_from_storage, write_local_db, Collection};
+
+/// `PRAGMA user_version` of a database the projects JSON file was imported into.
+const IMPORTED: i32 = 1;
+
+/// Keeps collections in an embedded SQLite database, one row per collection.
+///
+/// Rows hold the collection as JSON so the schema does not have to follow every
+/// change to `Collection`; the `position` column mirrors the collection's field
+/// so listing can be ordered without parsing every row.
+pub struct SqliteStore {
+    path: String,
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let connection = Connection::open(path).map_err(sql_error)?;
+        connection
+            .execute(
+                "CREATE TABLE IF NOT EXISTS collections (
+                    id TEXT PRIMARY KEY,
+                    position INTEGER NOT NULL,
+                    data TEXT NOT NULL
+                )",
+                [],
+            )
+            .map_err(sql_error)?;
+        // Rows written before collections carried a position only had the column.
+        connection
+            .execute(
+                "UPDATE collections SET data = json_set(data, '$.position', position)
+                 WHERE json_extract(data, '$.position') IS NULL",
+                [],
+            )
+            .map_err(sql_error)?;
+        // Rows written before statuses existed were all public.
+        connection
+            .execute(
+                "UPDATE collections SET data = json_set(data, '$.status', 'published')
+                 WHERE json_extract(data, '$.status') IS NULL",
+                [],
+            )
+            .map_err(sql_error)?;
+        Ok(SqliteStore {
+            path: path.to_string(),
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Seeds the database from a projects JSON file the first time it is opened.
+    /// The import is recorded in `PRAGMA user_version`, so a database emptied
+    /// later is not refilled from the file. Databases that already hold
+    /// collections are only marked as imported. A file that exists but cannot be
+    /// loaded is retried on the next start.
+    pub fn import_once(&self, json_path: &str) -> Result<()> {
+        let mut connection = self.connection()?;
+        let version: i32 = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(sql_error)?;
+        if version >= IMPORTED {
+            return Ok(());
+        }
+        let empty: bool = connection
+            .query_row("SELECT NOT EXISTS (SELECT 1 FROM collections)", [], |row| {
+                row.get(0)
+            })
+            .map_err(sql_error)?;
+        let collections = if !empty {
+            Vec::new()
+        } else {
+            match load_from_storage(json_path) {
+                Ok(collections) => {
+                    println!(
+                        "Importing {} collections from \"{}\"...",
+                        collections.len(),
+                        json_path
+                    );
+                    collections
+                }
+                Err(error) if error.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(error) => {
+                    eprintln!("Nothing imported into the projects database: {}", error);
+                    return Ok(());
+                }
+            }
+        };
+        let transaction = connection.transaction().map_err(sql_error)?;
+        for collection in collections {
+            let data = serde_json::to_string(&collection)?;
+            transaction
+                .execute(
+                    "INSERT INTO collections (id, position, data) VALUES (?1, ?2, ?3)",
+                    params![collection.id.to_string(), collection.position, data],
+                )
+                .map_err(sql_error)?;
+        }
+        transaction
+            .pragma_update(None, "user_version", IMPORTED)
+            .map_err(sql_error)?;
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn connection(&self) -> Result<MutexGuard<'_, Connection>> {
+        self.connection
+            .lock()
+            .map_err(|_| Error::other("Projects database lock is poisoned."))
+    }
+}
+
+impl Store for SqliteStore {
+    fn list(&self) -> Result<Vec<Collection>> {
+        let connection = self.connection()?;
+        let mut statement = connection
+            .prepare("SELECT data FROM collections ORDER BY position, rowid")
+            .map_err(sql_error)?;
+        let rows = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(sql_error)?;
+        let mut collections = Vec::new();
+        for row in rows {
+            collections.push(parse_row(&row.map_err(sql_error)?)?);
+        }
+        Ok(collections)
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<Collection>> {
+        let data = self
+            .connection()?
+            .query_row(
+                "SELECT data FROM collections WHERE id = ?1",
+                params![id.to_string()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        data.map(|data| parse_row(&data)).transpose()
+    }
+
+    fn insert(&self, collection: Collection) -> Result<Collection> {
+        let data = serde_json::to_string(&collection)?;
+        let connection = self.connection()?;
+        let result = connection.execute(
+            "INSERT INTO collections (id, position, data) VALUES (?1, ?2, ?3)",
+            params![collection.id.to_string(), collection.position, data],
+        );
+        match result {
+            Ok(_) => Ok(collection),
+            Err(rusqlite::Error::SqliteFailure(error, _))
+                if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("Collection {} already exists", collection.id),
+                ))
+            }
+            Err(error) => Err(sql_error(error)),
+        }
+    }
+
+    fn update(&self, collection: Collection) -> Result<Option<Collection>> {
+        let data = serde_json::to_string(&collection)?;
+        let changed = self
+            .connection()?
+            .execute(
+                "UPDATE collections SET position = ?2, data = ?3 WHERE id = ?1",
+                params![collection.id.to_string(), collection.position, data],
+            )
+            .map_err(sql_error)?;
+        Ok((changed > 0).then_some(collection))
+    }
+
+    fn update_all(&self, collections: Vec<Collection>) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        for collection in collections {
+            let data = serde_json::to_string(&collection)?;
+            let changed = transaction
+                .execute(
+                    "UPDATE collections SET position = ?2, data = ?3 WHERE id = ?1",
+                    params![collection.id.to_string(), collection.position, data],
+                )
+                .map_err(sql_error)?;
+            if changed == 0 {
+                // Dropping the transaction rolls back the rows already updated.
+                return Err(missing(collection.id));
+            }
+        }
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn replace_all(&self, collections: Vec<Collection>) -> Result<()> {
+        let mut connection = self.connection()?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        transaction
+            .execute("DELETE FROM collections", [])
+            .map_err(sql_error)?;
+        for collection in collections {
+            let data = serde_json::to_string(&collection)?;
+            let result = transaction.execute(
+                "INSERT INTO collections (id, position, data) VALUES (?1, ?2, ?3)",
+                params![collection.id.to_string(), collection.position, data],
+            );
+            match result {
+                Ok(_) => {}
+                Err(rusqlite::Error::SqliteFailure(error, _))
+                    if error.code == rusqlite::ErrorCode::ConstraintViolation =>
+                {
+                    return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("Collection {} is listed twice", collection.id),
+                    ));
+                }
+                Err(error) => return Err(sql_error(error)),
+            }
+        }
+        transaction.commit().map_err(sql_error)
+    }
+
+    fn delete(&self, id: Uuid) -> Result<Option<Collection>> {
+        let connection = self.connection()?;
+        let data = connection
+            .query_row(
+                "SELECT data FROM collections WHERE id = ?1",
+                params![id.to_string()],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()
+            .map_err(sql_error)?;
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        connection
+            .execute(
+                "DELETE FROM collections WHERE id = ?1",
+                params![id.to_string()],
+            )
+            .map_err(sql_error)?;
+        parse_row(&data).map(Some)
+    }
+
+    fn snapshot(&self, path: &str) -> Result<()> {
+        write_local_db(path, self.list()?).map(|_| ())
+    }
+
+    fn changed_at(&self) -> Result<Option<SystemTime>> {
+        file_changed_at(&self.path)
+    }
+}
+
+fn parse_row(data: &str) -> Result<Collection> {
+    serde_json::from_str::<Collection>(data).map_err(|error| {
+        eprintln!("Stored collection data structure is incorrect: {}", error);
+        error.into()
+    })
+}
+
+fn sql_error(error: rusqlite::Error) -> Error {
+    eprintln!("Projects database error: {}", error);
+    Error::other(error)
+}
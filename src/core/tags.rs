@@ -0,0 +1,113 @@
+use std::{cmp::Reverse, collections::BTreeMap};
+
+use serde::Serialize;
+
+use crate::core::data::Collection;
+
+/// A tag and the number of collections carrying it.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// The form every tag is stored in: lowercase, without dots, with runs of
+/// whitespace collapsed to one space. "UI", "ui" and "U.I." all become "ui".
+pub fn normalize_tag(tag: &str) -> String {
+    tag.replace('.', "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Normalizes a collection's tags in place, dropping duplicates but keeping
+/// the order they were first given in.
+pub fn normalize_tags(tags: &mut Vec<String>) {
+    let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+    for tag in tags.iter() {
+        let tag = normalize_tag(tag);
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    *tags = normalized;
+}
+
+/// Every tag in use with its count, most used first. Tags stored before
+/// normalization existed are counted under their normalized form.
+pub fn tag_counts(collections: &[Collection]) -> Vec<TagCount> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for collection in collections {
+        let mut tags = collection.tags.clone();
+        normalize_tags(&mut tags);
+        for tag in tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<TagCount> = counts
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+    counts.sort_by_key(|count| Reverse(count.count));
+    counts
+}
+
+/// Replaces every tag matching one of `sources` with `target`. Returns whether
+/// the collection changed.
+pub fn retag(collection: &mut Collection, sources: &[String], target: &str) -> bool {
+    let sources: Vec<String> = sources.iter().map(|tag| normalize_tag(tag)).collect();
+    if !collection
+        .tags
+        .iter()
+        .any(|tag| sources.contains(&normalize_tag(tag)))
+    {
+        return false;
+    }
+    for tag in collection.tags.iter_mut() {
+        if sources.contains(&normalize_tag(tag)) {
+            *tag = target.to_string();
+        }
+    }
+    normalize_tags(&mut collection.tags);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_tag, retag, tag_counts, TagCount};
+    use crate::core::data::Collection;
+
+    #[test]
+    fn normalizes_spelling_variants() {
+        for tag in ["UI", "ui", "U.I.", " u.i "] {
+            assert_eq!(normalize_tag(tag), "ui");
+        }
+        assert_eq!(normalize_tag("Brand   Identity"), "brand identity");
+    }
+
+    #[test]
+    fn merges_tags_across_collections() {
+        let mut first = Collection::default(Vec::new());
+        first.tags = vec!["UI".to_string(), "Web".to_string()];
+        let mut second = Collection::default(Vec::new());
+        second.tags = vec!["U.I.".to_string(), "ux".to_string()];
+        let mut collections = vec![first, second];
+        assert_eq!(
+            tag_counts(&collections)[0],
+            TagCount {
+                tag: "ui".to_string(),
+                count: 2
+            }
+        );
+
+        let sources = ["ux".to_string(), "ui".to_string()];
+        let changed: Vec<bool> = collections
+            .iter_mut()
+            .map(|collection| retag(collection, &sources, "design"))
+            .collect();
+        assert_eq!(changed, [true, true]);
+        assert_eq!(collections[0].tags, ["design", "web"]);
+        assert_eq!(collections[1].tags, ["design"]);
+    }
+}
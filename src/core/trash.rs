@@ -0,0 +1,180 @@
+use std::{
+    fs,
+    io::{ErrorKind, Result},
+};
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::core::{
+    data::{upgrade_status, Collection},
+    settings::Settings,
+    utils::{parse_timestamp, timestamp, write_json_atomic},
+};
+
+/// A deleted collection waiting to be restored or purged.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Deleted {
+    pub collection: Collection,
+    pub deleted_at: String,
+    /// Name of the API key whose session deleted the collection.
+    pub deleted_by: String,
+}
+
+impl Deleted {
+    pub fn new(collection: Collection, author: &str) -> Self {
+        Deleted {
+            collection,
+            deleted_at: timestamp(),
+            deleted_by: author.to_string(),
+        }
+    }
+}
+
+/// Deleted collections, kept in one JSON file next to the projects file until
+/// they are restored or their retention period runs out.
+pub struct Trash {
+    path: String,
+    /// Days a deleted collection is kept; 0 keeps them until restored.
+    retention_days: u16,
+}
+
+impl Trash {
+    pub fn new(path: &str, retention_days: u16) -> Self {
+        Trash {
+            path: path.to_string(),
+            retention_days,
+        }
+    }
+
+    /// The trash file kept next to the projects file.
+    pub fn open(settings: &Settings) -> Self {
+        Trash::new(
+            &format!(
+                "{}/{}.trash.json",
+                settings.local_projects_path.value, settings.projects_file_name.value
+            ),
+            settings.trash_retention_days.value,
+        )
+    }
+
+    /// Every deleted collection, most recently deleted last. Collections
+    /// deleted before statuses existed load as published, like the projects
+    /// file does.
+    pub fn list(&self) -> Result<Vec<Deleted>> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        let mut entries = serde_json::from_slice::<Vec<Value>>(&bytes)?;
+        for entry in entries.iter_mut() {
+            if let Some(collection) = entry.get_mut("collection") {
+                upgrade_status(collection);
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<serde_json::Result<Vec<Deleted>>>()?)
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<Option<Deleted>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .find(|deleted| deleted.collection.id == id))
+    }
+
+    /// Adds a deleted collection, replacing an older entry with the same id.
+    pub fn put(&self, deleted: Deleted) -> Result<()> {
+        let mut entries = self.list()?;
+        entries.retain(|entry| entry.collection.id != deleted.collection.id);
+        entries.push(deleted);
+        write_json_atomic(&self.path, &entries)
+    }
+
+    /// Removes a collection from the trash, returning it if it was there.
+    pub fn take(&self, id: Uuid) -> Result<Option<Deleted>> {
+        let mut entries = self.list()?;
+        let Some(index) = entries
+            .iter()
+            .position(|deleted| deleted.collection.id == id)
+        else {
+            return Ok(None);
+        };
+        let deleted = entries.remove(index);
+        write_json_atomic(&self.path, &entries)?;
+        Ok(Some(deleted))
+    }
+
+    /// Permanently drops collections deleted longer ago than the retention
+    /// period and returns them. Entries with an unreadable time are kept.
+    pub fn purge(&self) -> Result<Vec<Deleted>> {
+        if self.retention_days == 0 {
+            return Ok(Vec::new());
+        }
+        let cutoff = Utc::now() - Duration::days(i64::from(self.retention_days));
+        let (expired, kept): (Vec<Deleted>, Vec<Deleted>) =
+            self.list()?.into_iter().partition(|deleted| {
+                parse_timestamp(&deleted.deleted_at).is_some_and(|time| time < cutoff)
+            });
+        if !expired.is_empty() {
+            write_json_atomic(&self.path, &kept)?;
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::{Deleted, Trash};
+    use crate::core::{
+        data::{Collection, Status},
+        utils::TempDir,
+    };
+
+    #[test]
+    fn purges_only_expired_entries() {
+        let dir = TempDir::create();
+        let trash = Trash::new(&dir.path("trash.json"), 30);
+
+        let mut old = Deleted::new(Collection::default(Vec::new()), "admin");
+        old.deleted_at = "2020-01-01 00:00:00 UTC".to_string();
+        let recent = Deleted::new(Collection::default(Vec::new()), "admin");
+        let recent_id = recent.collection.id;
+        trash.put(old).unwrap();
+        trash.put(recent).unwrap();
+
+        assert_eq!(trash.purge().unwrap().len(), 1);
+        let left = trash.list().unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].collection.id, recent_id);
+        assert!(trash.take(recent_id).unwrap().is_some());
+        assert!(trash.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn loads_entries_deleted_before_statuses_as_published() {
+        let dir = TempDir::create();
+        let path = dir.path("trash.json");
+        let mut deleted = json!(Deleted::new(Collection::default(Vec::new()), "admin"));
+        deleted["collection"]
+            .as_object_mut()
+            .unwrap()
+            .remove("status");
+        fs::write(&path, json!([deleted]).to_string()).unwrap();
+
+        let trash = Trash::new(&path, 30);
+        assert_eq!(
+            trash.list().unwrap()[0].collection.status,
+            Status::Published
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Result, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Format of `last_modified` and other stored times.
+pub const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+/// Current time in the format used for `last_modified` fields.
+pub fn timestamp() -> String {
+    Utc::now().format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Parses a time written by `timestamp`, an RFC 3339 time or a plain `YYYY-MM-DD`
+/// date (taken as midnight UTC).
+pub fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, TIMESTAMP_FORMAT) {
+        return Some(time.and_utc());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|time| time.and_utc())
+}
+
+/// Lowercase hex encoding, used for key material and checksums.
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Compares two byte strings in time that depends only on their lengths.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Writes `value` to `path` as pretty JSON without ever leaving a partial file behind.
+///
+/// The data goes to a temporary file in the same directory, is synced to disk and
+/// only then renamed over `path`, so readers see either the old or the new file.
+pub fn write_json_atomic<T: Serialize + ?Sized>(path: &str, value: &T) -> Result<()> {
+    let path = Path::new(path);
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, Uuid::new_v4().simple()));
+    match write_and_replace(&temp_path, path, value) {
+        Ok(_) => {
+            // Persist the rename itself; not every platform can open a directory.
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+            Ok(())
+        }
+        Err(error) => {
+            let _ = fs::remove_file(&temp_path);
+            Err(error)
+        }
+    }
+}
+
+fn write_and_replace<T: Serialize + ?Sized>(
+    temp_path: &Path,
+    path: &Path,
+    value: &T,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+    let file = writer.into_inner().map_err(|error| error.into_error())?;
+    file.sync_all()?;
+    fs::rename(temp_path, path)
+}
+
+/// A fresh directory under the system temp directory for a test, removed with
+/// everything in it when dropped.
+#[cfg(test)]
+pub struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn create() -> Self {
+        let dir = std::env::temp_dir().join(format!("folio-test-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.0
+    }
+
+    /// Path of `name` inside the directory.
+    pub fn path(&self, name: &str) -> String {
+        self.0.join(name).to_str().unwrap().to_string()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde::{ser::Error, ser::SerializeSeq, Serialize, Serializer};
+
+    use super::{write_json_atomic, TempDir};
+
+    /// Starts writing a list, then fails as if the process died halfway through.
+    struct FailsMidway;
+
+    impl Serialize for FailsMidway {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(None)?;
+            seq.serialize_element("partial")?;
+            Err(S::Error::custom("simulated failure mid-write"))
+        }
+    }
+
+    #[test]
+    fn failed_write_keeps_previous_file() {
+        let dir = TempDir::create();
+        let path = dir.path("projects.json");
+
+        write_json_atomic(&path, &vec!["original"]).unwrap();
+        let before = fs::read_to_string(&path).unwrap();
+
+        assert!(write_json_atomic(&path, &FailsMidway).is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), before);
+        assert_eq!(
+            fs::read_dir(dir.root()).unwrap().count(),
+            1,
+            "temp file left behind"
+        );
+    }
+
+    #[test]
+    fn creates_missing_directories() {
+        let dir = TempDir::create();
+        let path = dir.path("nested/projects.json");
+
+        write_json_atomic(&path, &vec![1, 2, 3]).unwrap();
+        let written: Vec<u32> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::core::{
+    data::{Collection, Status, PLACEHOLDER},
+    utils::parse_timestamp,
+};
+
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_TEXT_LENGTH: usize = 10_000;
+const MAX_TAG_LENGTH: usize = 50;
+const MAX_URL_LENGTH: usize = 2048;
+
+/// One problem with one field of a submitted collection.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    /// Path to the field, e.g. `keypoints[2].title`.
+    pub field: String,
+    pub message: String,
+}
+
+/// Checks a collection before it is stored. Returns every problem found rather
+/// than stopping at the first, so clients can show them all at once.
+pub fn validate(collection: &Collection) -> Result<(), Vec<FieldError>> {
+    let mut errors = Errors::default();
+
+    errors.required("client", &collection.client, MAX_NAME_LENGTH);
+    errors.url("client_logo", &collection.client_logo);
+    errors.hex_color("accent_color", &collection.accent_color);
+    errors.required("title", &collection.title, MAX_NAME_LENGTH);
+    for (index, tag) in collection.tags.iter().enumerate() {
+        errors.required(&format!("tags[{}]", index), tag, MAX_TAG_LENGTH);
+    }
+    errors.url("featured", &collection.featured);
+    errors.max_length("summary", &collection.summary, MAX_TEXT_LENGTH);
+    match &collection.publish_at {
+        Some(time) if parse_timestamp(time).is_none() => errors.push(
+            "publish_at".to_string(),
+            format!("\"{}\" is not a valid time.", time),
+        ),
+        None if collection.status == Status::Scheduled => errors.push(
+            "publish_at".to_string(),
+            "A scheduled collection needs a publish_at time.".to_string(),
+        ),
+        _ => {}
+    }
+
+    let mut keypoint_ids = HashSet::new();
+    for (index, keypoint) in collection.keypoints.iter().enumerate() {
+        let field = format!("keypoints[{}]", index);
+        if !keypoint_ids.insert(keypoint.id) {
+            errors.push(
+                format!("{}.id", field),
+                format!("Duplicate keypoint id {}.", keypoint.id),
+            );
+        }
+        for (url_index, url) in keypoint.featured.iter().enumerate() {
+            errors.url(&format!("{}.featured[{}]", field, url_index), url);
+        }
+        errors.required(
+            &format!("{}.title", field),
+            &keypoint.title,
+            MAX_NAME_LENGTH,
+        );
+        errors.max_length(
+            &format!("{}.summary", field),
+            &keypoint.summary,
+            MAX_TEXT_LENGTH,
+        );
+    }
+
+    let mut text_field_ids = HashSet::new();
+    for (index, text_field) in collection.text_fields.iter().enumerate() {
+        let field = format!("text_fields[{}]", index);
+        if !text_field_ids.insert(text_field.id) {
+            errors.push(
+                format!("{}.id", field),
+                format!("Duplicate text field id {}.", text_field.id),
+            );
+        }
+        errors.required(
+            &format!("{}.name", field),
+            &text_field.name,
+            MAX_NAME_LENGTH,
+        );
+        errors.max_length(
+            &format!("{}.value", field),
+            &text_field.value,
+            MAX_TEXT_LENGTH,
+        );
+    }
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.0)
+    }
+}
+
+#[derive(Default)]
+struct Errors(Vec<FieldError>);
+
+impl Errors {
+    fn push(&mut self, field: String, message: String) {
+        self.0.push(FieldError { field, message });
+    }
+
+    fn required(&mut self, field: &str, value: &str, max: usize) {
+        if value.trim().is_empty() {
+            self.push(field.to_string(), "Must not be empty.".to_string());
+        } else {
+            self.max_length(field, value, max);
+        }
+    }
+
+    fn max_length(&mut self, field: &str, value: &str, max: usize) {
+        if value.chars().count() > max {
+            self.push(
+                field.to_string(),
+                format!("Must be at most {} characters.", max),
+            );
+        }
+    }
+
+    fn hex_color(&mut self, field: &str, value: &str) {
+        let valid = match value.strip_prefix('#') {
+            Some(digits) => {
+                matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            None => false,
+        };
+        if !valid {
+            self.push(
+                field.to_string(),
+                "Must be a hex color such as #cacaca or #fff.".to_string(),
+            );
+        }
+    }
+
+    /// Accepts absolute http(s) URLs, root-relative paths, the placeholder, or nothing.
+    fn url(&mut self, field: &str, value: &str) {
+        if value.is_empty() || value == PLACEHOLDER {
+            return;
+        }
+        let valid = if let Some(rest) = value
+            .strip_prefix("https://")
+            .or_else(|| value.strip_prefix("http://"))
+        {
+            let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            !host.is_empty()
+        } else {
+            value.starts_with('/') && !value.starts_with("//")
+        };
+        if !valid || value.chars().any(char::is_whitespace) {
+            self.push(
+                field.to_string(),
+                format!(
+                    "Must be an http(s) URL, a path starting with /, or \"{}\".",
+                    PLACEHOLDER
+                ),
+            );
+        } else {
+            self.max_length(field, value, MAX_URL_LENGTH);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate;
+    use crate::core::data::Collection;
+
+    #[test]
+    fn accepts_default_collection() {
+        assert_eq!(validate(&Collection::default(Vec::new())), Ok(()));
+    }
+
+    #[test]
+    fn reports_every_invalid_field() {
+        let mut collection = Collection::default(Vec::new());
+        collection.accent_color = "cacaca".to_string();
+        collection.title = "  ".to_string();
+        collection.client_logo = "javascript:alert(1)".to_string();
+        collection.featured = "https://cdn.example.com/a.png".to_string();
+        collection.keypoints.push(collection.keypoints[0].clone());
+        collection.keypoints[1].featured = vec!["//evil.example.com".to_string()];
+        collection.tags.push("x".repeat(51));
+
+        let fields: Vec<String> = validate(&collection)
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "client_logo",
+                "accent_color",
+                "title",
+                "tags[1]",
+                "keypoints[1].id",
+                "keypoints[1].featured[0]"
+            ]
+        );
+    }
+}
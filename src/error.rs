@@ -0,0 +1,149 @@
+use std::{fmt, io};
+
+use actix_web::{
+    error::JsonPayloadError,
+    http::{header::ETag, StatusCode},
+    HttpResponse, ResponseError,
+};
+use serde_json::{json, Value};
+
+use crate::core::validation::FieldError;
+
+/// Every error a request can end in. Rendered as `{code, message, details}` JSON.
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    MethodNotAllowed(String),
+    Conflict(String),
+    /// The `If-Match` header did not match; carries the current `ETag`.
+    PreconditionFailed(String, ETag),
+    PayloadTooLarge(String),
+    UnsupportedMediaType(String),
+    /// The body parsed but failed validation; carries one error per bad field.
+    Unprocessable(Vec<FieldError>),
+    Unavailable(String),
+    /// An I/O or storage failure. Details are logged, never sent to the client.
+    Internal(io::Error),
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::MethodNotAllowed(_) => "method_not_allowed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PreconditionFailed(..) => "precondition_failed",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::UnsupportedMediaType(_) => "unsupported_media_type",
+            ApiError::Unprocessable(_) => "validation_failed",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Internal(_) => "internal",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message)
+            | ApiError::MethodNotAllowed(message)
+            | ApiError::Conflict(message)
+            | ApiError::PreconditionFailed(message, _)
+            | ApiError::PayloadTooLarge(message)
+            | ApiError::UnsupportedMediaType(message)
+            | ApiError::Unavailable(message) => message.clone(),
+            ApiError::Unprocessable(errors) => {
+                format!("{} field(s) failed validation.", errors.len())
+            }
+            ApiError::Internal(_) => "Internal server error.".to_string(),
+        }
+    }
+
+    fn details(&self) -> Value {
+        match self {
+            ApiError::PreconditionFailed(_, ETag(etag)) => json!({ "etag": etag.to_string() }),
+            ApiError::Unprocessable(errors) => json!({ "errors": errors }),
+            _ => Value::Null,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Internal(error) => write!(f, "{}", error),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PreconditionFailed(..) => StatusCode::PRECONDITION_FAILED,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(error) = self {
+            eprintln!("Request failed: {}", error);
+        }
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::PreconditionFailed(_, etag) = self {
+            response.insert_header(etag.clone());
+        }
+        response.json(json!({
+            "code": self.code(),
+            "message": self.message(),
+            "details": self.details(),
+        }))
+    }
+}
+
+impl From<io::Error> for ApiError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::AlreadyExists => ApiError::Conflict(error.to_string()),
+            io::ErrorKind::InvalidInput => ApiError::BadRequest(error.to_string()),
+            _ => ApiError::Internal(error),
+        }
+    }
+}
+
+impl From<JsonPayloadError> for ApiError {
+    fn from(error: JsonPayloadError) -> Self {
+        match error {
+            JsonPayloadError::OverflowKnownLength { .. } | JsonPayloadError::Overflow { .. } => {
+                ApiError::PayloadTooLarge(format!("Request body is too large: {}", error))
+            }
+            JsonPayloadError::ContentType => {
+                ApiError::UnsupportedMediaType("Request body must be application/json.".to_string())
+            }
+            _ => ApiError::BadRequest(format!("Invalid JSON body: {}", error)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(error: serde_json::Error) -> Self {
+        ApiError::Internal(error.into())
+    }
+}
@@ -1,21 +1,62 @@
 use core::settings::Settings;
 use std::io::Result;
 
-use crate::{auth::get_local_passkey, core::data::{load_from_cdn, load_from_storage, write_local_db, Collection}};
+use actix_web::web::Data;
+
+use crate::{
+    auth::{keys::KEYS_PATH, session::Sessions},
+    core::{
+        backup::Backups,
+        data::{load_from_cdn, load_from_storage, write_local_db, Collection},
+        history::History,
+        store::open_store,
+        trash::Trash,
+    },
+    state::{publish_scheduled, purge_trash, schedule_backups, watch_store, AppState},
+};
 
 mod core;
+mod error;
 mod server;
 mod auth;
+mod state;
 
 //implement periodic core checks and creation if they dont exist (folders, etc)
 
 #[actix_web::main]
 async fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("keys") {
+        return auth::keys::run_command(&args[1..]);
+    }
     let settings = Settings::load().unwrap();
-    let _ = init_local_files().await;
+    // The projects database does not read the JSON file past its first import.
+    if settings.storage_backend.value != "sqlite" {
+        init_local_files().await;
+    }
+    let store = open_store(&settings)?;
+    let backups = Backups::open(&settings);
+    match backups.create(&*store, "startup") {
+        Ok(snapshot) => println!("Local backup \"{}\" created successfully!\n", snapshot.name),
+        Err(error) => eprintln!("Failed to create backup: {}", error),
+    }
     println!("\nStarting administrative server...");
     let server_addr = format!("{}:{}", settings.ipv4_addr.value, settings.port.value);
-    let server = server::start_server(server_addr);
+    let state = Data::new(AppState::new(
+        store,
+        History::open(&settings),
+        Trash::open(&settings),
+        backups,
+    )?);
+    watch_store(state.clone());
+    purge_trash(state.clone());
+    publish_scheduled(state.clone());
+    schedule_backups(state.clone(), settings.backup_interval_minutes.value);
+    let sessions = Data::new(Sessions::new(
+        KEYS_PATH,
+        settings.session_ttl_minutes.value,
+    )?);
+    let server = server::start_server(server_addr, state, sessions);
     server.await?;
     Ok(())
 }
@@ -47,9 +88,9 @@ async fn get_current_projects(
     }
 }
 
-fn init_paths() -> [String; 3] {
+fn init_paths() -> [String; 2] {
     let settings = Settings::load().unwrap();
-    return [
+    [
         format!(
             "{}/{}.json",
             settings.local_projects_path.value, settings.projects_file_name.value
@@ -58,15 +99,11 @@ fn init_paths() -> [String; 3] {
             "{}/{}.json",
             settings.remote_url.value, settings.projects_file_name.value
         ),
-        format!(
-            "{}/{}.json",
-            settings.local_backup_path.value, settings.projects_file_name.value
-        ),
-    ];
+    ]
 }
 
 async fn init_local_files() {
-    let [local_projects_path, remote_projects_path, local_backup_path] = init_paths();
+    let [local_projects_path, remote_projects_path] = init_paths();
     let mut current_projects: Vec<Collection> = Vec::new();
 
     match get_current_projects(&local_projects_path, &remote_projects_path).await {
@@ -74,17 +111,9 @@ async fn init_local_files() {
             current_projects = projects;
             // refactor to handle better i.e. if local projects are loaded, don't overwrite.
             println!("\nSyncing local files...");
-            match write_local_db(&local_projects_path, current_projects.clone()) {
+            match write_local_db(&local_projects_path, current_projects) {
                 Ok(_) => {
                     println!("Local working file created successfully!\n");
-                    match write_local_db(&local_backup_path, current_projects) {
-                        Ok(_) => {
-                            println!("Local backup file created successfully!\n");
-                        }
-                        Err(error) => {
-                            eprintln!("Failed to create backup file: {}", error);
-                        }
-                    }
                 }
                 Err(error) => {
                     eprintln!("Failed to created working file: {}", error);
@@ -97,16 +126,8 @@ async fn init_local_files() {
             println!("Creating new file...");
             let new_collections = vec![Collection::default(current_projects)];
             match write_local_db(&local_projects_path, new_collections) {
-                Ok(current_projects) => {
+                Ok(_) => {
                     println!("Database created successfully!");
-                    match write_local_db(&local_backup_path, current_projects) {
-                        Ok(_) => {
-                            println!("Local backup file created successfully!\n");
-                        }
-                        Err(error) => {
-                            eprintln!("Failed to create backup file: {}", error);
-                        }
-                    }
                 }
                 Err(error) => {
                     eprintln!("Could not create database: {}", error);
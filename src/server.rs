@@ -1,40 +1,76 @@
-use std::io::Result;
+use std::{
+    fmt::Display,
+    io::{Error, ErrorKind, Result},
+};
 
 use actix_cors::Cors;
 use actix_web::{
-    web::{self, resource, scope, Json},
-    App, HttpResponse, HttpServer,
+    http::header::{
+        self, ContentDisposition, ContentType, DispositionParam, DispositionType, ETag, EntityTag,
+        Header, HeaderName, IfMatch,
+    },
+    web::{
+        self, resource, scope, Data, Json, JsonConfig, Path, PathConfig, Query, QueryConfig,
+        ReqData, ServiceConfig,
+    },
+    App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Resource, Route,
 };
-use actix_web_httpauth::extractors::bearer::BearerAuth;
+use actix_web_httpauth::middleware::HttpAuthentication;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
 
 use crate::{
-    auth::check_auth,
-    core::data::{load_from_storage, write_local_db, Collection},
-    init_local_files,
+    auth::{
+        keys::Role,
+        session::{Claims, Sessions},
+        validator,
+    },
+    core::{
+        backup::{Snapshot, PRE_RESTORE},
+        data::{next_position, Collection, Keypoint, Nested, Status, TextField},
+        history::Action,
+        patch::{json_patch, merge_patch, PatchError},
+        query::{ListQuery, MAX_LIMIT},
+        tags::{normalize_tag, normalize_tags, retag, tag_counts},
+        trash::Deleted,
+        utils::{parse_timestamp, timestamp, TIMESTAMP_FORMAT},
+        validation::{validate, FieldError},
+    },
+    error::ApiError,
+    state::AppState,
 };
 
-const LOCAL_PROJECTS_PATH: &str = "data";
-const PROJECTS_FILE_NAME: &str = "projects";
+type ApiResult = std::result::Result<HttpResponse, ApiError>;
+
+const JSON_PATCH_TYPE: &str = "application/json-patch+json";
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
 
-pub async fn start_server(addr: String) -> Result<()> {
-    HttpServer::new(|| {
+pub async fn start_server(
+    addr: String,
+    state: Data<AppState>,
+    sessions: Data<Sessions>,
+) -> Result<()> {
+    HttpServer::new(move || {
         App::new()
-            .service(
-                scope("/v1")
-                    .service(
-                        resource("/projects")
-                            .route(web::get().to(get_handler))
-                            .route(web::put().to(update_handler))
-                            .route(web::post().to(create_handler))
-                            .route(web::delete().to(del_handler)),
-                    )
-                    .service(resource("/folio").route(web::get().to(status_handler))),
-            )
+            .app_data(state.clone())
+            .app_data(sessions.clone())
+            .configure(configure)
             .wrap(
                 Cors::default()
                     .allow_any_origin()
                     .allow_any_header()
-                    .allow_any_method(),
+                    .allow_any_method()
+                    .expose_headers([
+                        header::ETAG,
+                        header::LOCATION,
+                        header::CONTENT_DISPOSITION,
+                        HeaderName::from_static(TOTAL_COUNT_HEADER),
+                        HeaderName::from_static(NEXT_CURSOR_HEADER),
+                    ]),
             )
     })
     .bind(&addr)?
@@ -42,113 +78,2031 @@ pub async fn start_server(addr: String) -> Result<()> {
     .await
 }
 
-async fn get_handler() -> HttpResponse {
-    let local_projects_path = format!("{}/{}.json", LOCAL_PROJECTS_PATH, PROJECTS_FILE_NAME);
-    match load_from_storage(&local_projects_path) {
-        Ok(projects) => {
-            let value = serde_json::to_value(projects).unwrap();
-            HttpResponse::Ok().json(value)
-        }
-        Err(error) => {
-            eprintln!("Failed to load projects data: {}", error);
-            println!("Re-initializing files...");
-            let _ = init_local_files();
-            HttpResponse::Ok().json("Failed to load local data. Please refresh.")
-        }
-    }
-}
-
-async fn create_handler(collection: Json<Collection>, remote_key: BearerAuth) -> HttpResponse {
-    if let Ok(_) = check_auth(remote_key.token().to_string()) {
-        let local_projects_path = format!("{}/{}.json", LOCAL_PROJECTS_PATH, PROJECTS_FILE_NAME);
-        let collection_title = collection.title.clone();
-        match load_from_storage(&local_projects_path) {
-            Ok(mut collections) => {
-                let collection = collection.into_inner();
-                collections.push(collection);
-                match write_local_db(&local_projects_path, collections) {
-                    Ok(_) => {
-                        println!("Added \"{}\"", collection_title);
-                        HttpResponse::Ok().body(format!("Added \"{}\"", collection_title))
-                    }
-                    Err(error) => {
-                        eprintln!("Failed to add \"{}\"", collection_title);
-                        HttpResponse::from_error(error)
-                    }
-                }
-            }
-            Err(error) => {
-                eprintln!("Failed to add \"{}\"", collection_title);
-                HttpResponse::from_error(error)
-            }
+/// Registers every route. Expects `Data<AppState>` and `Data<Sessions>` to be
+/// provided by the app.
+pub fn configure(cfg: &mut ServiceConfig) {
+    cfg.app_data(JsonConfig::default().error_handler(|error, _| ApiError::from(error).into()))
+        .app_data(QueryConfig::default().error_handler(|error, _| {
+            ApiError::BadRequest(format!("Invalid query: {}", error)).into()
+        }))
+        .app_data(PathConfig::default().error_handler(|error, _| {
+            ApiError::BadRequest(format!("Invalid path: {}", error)).into()
+        }))
+        .service(
+            scope("/v1")
+                .service(endpoint("/auth/login").route(web::post().to(login_handler)))
+                .service(endpoint("/auth/refresh").route(authenticated(
+                    Role::ReadOnly,
+                    web::post().to(refresh_handler),
+                )))
+                .service(endpoint("/auth/logout").route(authenticated(
+                    Role::ReadOnly,
+                    web::post().to(logout_handler),
+                )))
+                .service(
+                    endpoint("/projects")
+                        .route(optionally_authenticated(web::get().to(get_handler)))
+                        .route(authenticated(Role::Editor, web::post().to(create_handler))),
+                )
+                .service(
+                    endpoint("/projects/reorder")
+                        .route(authenticated(Role::Editor, web::post().to(reorder_handler))),
+                )
+                .service(
+                    endpoint("/projects/{id}")
+                        .route(optionally_authenticated(web::get().to(get_by_id_handler)))
+                        .route(authenticated(Role::Editor, web::put().to(update_handler)))
+                        .route(authenticated(Role::Editor, web::patch().to(patch_handler)))
+                        .route(authenticated(Role::Admin, web::delete().to(del_handler))),
+                )
+                .service(
+                    endpoint("/search")
+                        .route(optionally_authenticated(web::get().to(search_handler))),
+                )
+                .service(
+                    endpoint("/tags").route(optionally_authenticated(web::get().to(tags_handler))),
+                )
+                .service(endpoint("/tags/rename").route(authenticated(
+                    Role::Editor,
+                    web::post().to(rename_tag_handler),
+                )))
+                .service(endpoint("/tags/merge").route(authenticated(
+                    Role::Editor,
+                    web::post().to(merge_tags_handler),
+                )))
+                .service(endpoint("/projects/{id}/history").route(authenticated(
+                    Role::ReadOnly,
+                    web::get().to(history_handler),
+                )))
+                .service(
+                    endpoint("/projects/{id}/publish")
+                        .route(authenticated(Role::Editor, web::post().to(publish_handler))),
+                )
+                .service(
+                    endpoint("/projects/{id}/revert/{revision}")
+                        .route(authenticated(Role::Editor, web::post().to(revert_handler))),
+                )
+                .service(
+                    endpoint("/trash")
+                        .route(authenticated(Role::ReadOnly, web::get().to(trash_handler))),
+                )
+                .service(
+                    endpoint("/trash/{id}/restore")
+                        .route(authenticated(Role::Editor, web::post().to(restore_handler))),
+                )
+                .service(
+                    endpoint("/backups")
+                        .route(authenticated(Role::Admin, web::get().to(backups_handler))),
+                )
+                .service(endpoint("/backups/{name}").route(authenticated(
+                    Role::Admin,
+                    web::get().to(download_backup_handler),
+                )))
+                .service(endpoint("/backups/{name}/restore").route(authenticated(
+                    Role::Admin,
+                    web::post().to(restore_backup_handler),
+                )))
+                .configure(nested_routes::<Keypoint>)
+                .configure(nested_routes::<TextField>)
+                .service(endpoint("/folio").route(web::get().to(status_handler))),
+        )
+        .default_service(web::to(not_found_handler));
+}
+
+/// Routes for the items of type `T` nested in each collection.
+fn nested_routes<T: Nested>(cfg: &mut ServiceConfig) {
+    let path = format!("/projects/{{id}}/{}", T::PATH);
+    cfg.service(
+        endpoint(&path)
+            .route(optionally_authenticated(
+                web::get().to(list_nested_handler::<T>),
+            ))
+            .route(authenticated(
+                Role::Editor,
+                web::post().to(create_nested_handler::<T>),
+            )),
+    )
+    .service(endpoint(&format!("{}/reorder", path)).route(authenticated(
+        Role::Editor,
+        web::post().to(reorder_nested_handler::<T>),
+    )))
+    .service(
+        endpoint(&format!("{}/{{item_id}}", path))
+            .route(optionally_authenticated(
+                web::get().to(get_nested_handler::<T>),
+            ))
+            .route(authenticated(
+                Role::Editor,
+                web::put().to(update_nested_handler::<T>),
+            ))
+            .route(authenticated(
+                Role::Editor,
+                web::delete().to(del_nested_handler::<T>),
+            )),
+    );
+}
+
+/// Requires a session with at least the `required` role before the route's handler runs.
+fn authenticated(required: Role, route: Route) -> Route {
+    route.wrap(HttpAuthentication::with_fn(move |req, credentials| {
+        validator(req, credentials, required)
+    }))
+}
+
+/// Lets a route be read without a session, but checks any token that is sent so
+/// the handler can show signed-in readers more.
+fn optionally_authenticated(route: Route) -> Route {
+    route.wrap(HttpAuthentication::with_fn(|req, credentials| async move {
+        match credentials {
+            Some(credentials) => validator(req, Some(credentials), Role::ReadOnly).await,
+            None => Ok(req),
+        }
+    }))
+}
+
+/// A resource that answers unsupported methods with a JSON 405.
+fn endpoint(path: &str) -> Resource {
+    resource(path).default_service(web::to(method_not_allowed_handler))
+}
+
+fn not_found(id: Uuid) -> ApiError {
+    ApiError::NotFound(format!("No collection with id {}", id))
+}
+
+/// Readers with a session see every collection; everyone else only published ones.
+fn visible(claims: &Option<ReqData<Claims>>, collection: &Collection) -> bool {
+    claims.is_some() || collection.is_published()
+}
+
+/// A collection the reader may see. Hidden collections are reported as missing.
+fn find_visible(
+    state: &AppState,
+    claims: &Option<ReqData<Claims>>,
+    id: Uuid,
+) -> std::result::Result<Collection, ApiError> {
+    state
+        .get(id)
+        .filter(|collection| visible(claims, collection))
+        .ok_or_else(|| not_found(id))
+}
+
+/// The cached collections the reader may see, in display order.
+fn visible_collections(state: &AppState, claims: &Option<ReqData<Claims>>) -> Vec<Collection> {
+    state
+        .collections()
+        .iter()
+        .filter(|collection| visible(claims, collection))
+        .cloned()
+        .collect()
+}
+
+/// Normalizes a submitted collection, then validates it.
+fn prepare(collection: &mut Collection) -> std::result::Result<(), ApiError> {
+    normalize_tags(&mut collection.tags);
+    collection.order_keypoints();
+    validate(collection).map_err(ApiError::Unprocessable)
+}
+
+fn etag(collection: &Collection) -> ETag {
+    ETag(EntityTag::new_strong(collection.revision.to_string()))
+}
+
+/// Checks an optional `If-Match` header against the stored collection. Requests
+/// without the header are not subject to optimistic concurrency control.
+fn check_if_match(req: &HttpRequest, current: &Collection) -> std::result::Result<(), ApiError> {
+    if !req.headers().contains_key(header::IF_MATCH) {
+        return Ok(());
+    }
+    let matches = match IfMatch::parse(req) {
+        Ok(IfMatch::Any) => true,
+        Ok(IfMatch::Items(tags)) => {
+            let ETag(current) = etag(current);
+            tags.iter().any(|tag| tag.strong_eq(&current))
         }
+        Err(_) => false,
+    };
+    if matches {
+        Ok(())
     } else {
-        HttpResponse::Unauthorized().body("Unauthorized token.")
-    }
-}
-
-async fn update_handler(collection: Json<Collection>, remote_key: BearerAuth) -> HttpResponse {
-    if let Ok(_) = check_auth(remote_key.token().to_string()) {
-        let local_projects_path = format!("{}/{}.json", LOCAL_PROJECTS_PATH, PROJECTS_FILE_NAME);
-        let collection_title = collection.title.clone();
-        match load_from_storage(&local_projects_path) {
-            Ok(collections) => {
-                let collection = collection.into_inner();
-                let collections = collections
-                    .iter()
-                    .map(|item| {
-                        if item.id == collection.id {
-                            return collection.clone();
-                        } else {
-                            return item.clone();
-                        }
-                    })
-                    .collect();
-                match write_local_db(&local_projects_path, collections) {
-                    Ok(_) => {
-                        println!("Updated \"{}\"", collection_title);
-                        HttpResponse::Ok().body(format!("Updated \"{}\"", collection_title))
-                    }
-                    Err(error) => HttpResponse::from_error(error),
+        Err(ApiError::PreconditionFailed(
+            format!(
+                "Collection {} has changed since it was read (revision {})",
+                current.id, current.revision
+            ),
+            etag(current),
+        ))
+    }
+}
+
+/// Lists collections, optionally filtered, sorted and paged. The number of
+/// matches is sent in `X-Total-Count` and, when there are more pages, the cursor
+/// for the next one in `X-Next-Cursor`.
+async fn get_handler(
+    claims: Option<ReqData<Claims>>,
+    query: Query<ListQuery>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let page = query.apply(&visible_collections(&state, &claims))?;
+    let mut response = HttpResponse::Ok();
+    response.insert_header((TOTAL_COUNT_HEADER, page.total));
+    if let Some(cursor) = page.next_cursor {
+        response.insert_header((NEXT_CURSOR_HEADER, cursor));
+    }
+    Ok(response.json(page.items))
+}
+
+#[derive(Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+/// Full-text search over collection content, best matches first.
+async fn search_handler(
+    claims: Option<ReqData<Claims>>,
+    query: Query<SearchQuery>,
+    state: Data<AppState>,
+) -> ApiResult {
+    if query.q.trim().is_empty() {
+        return Err(ApiError::BadRequest(
+            "The search query q must not be empty.".to_string(),
+        ));
+    }
+    let limit = match query.limit {
+        Some(limit) if limit == 0 || limit > MAX_LIMIT => {
+            return Err(ApiError::BadRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_LIMIT
+            )))
+        }
+        Some(limit) => limit,
+        None => DEFAULT_SEARCH_LIMIT,
+    };
+    Ok(HttpResponse::Ok().json(state.search(&query.q, limit, claims.is_none())))
+}
+
+async fn get_by_id_handler(
+    claims: Option<ReqData<Claims>>,
+    id: Path<Uuid>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let collection = find_visible(&state, &claims, id.into_inner())?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+async fn create_handler(
+    claims: ReqData<Claims>,
+    collection: Json<Collection>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let mut collection = collection.into_inner();
+    prepare(&mut collection)?;
+    let _guard = state.lock();
+    if !collection.id.is_nil() {
+        return Err(match state.store.get(collection.id)? {
+            Some(_) => ApiError::Conflict(format!("Collection {} already exists", collection.id)),
+            None => ApiError::BadRequest("Collection ids are assigned by the server.".to_string()),
+        });
+    }
+    // New collections start as drafts unless archived outright.
+    check_status(collection.status)?;
+    collection.publish_at = None;
+    collection.id = Uuid::new_v4();
+    collection.position = next_position(&state.store.list()?);
+    collection.last_modified = timestamp();
+    collection.revision = 1;
+    let collection = state.store.insert(collection)?;
+    state.invalidate();
+    state.record(Action::Create, &claims.sub, None, Some(&collection));
+    println!("Added \"{}\"", collection.title);
+    Ok(HttpResponse::Created()
+        .insert_header((header::LOCATION, format!("/v1/projects/{}", collection.id)))
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+/// Replaces a collection. Bodies without a `status` keep the stored one.
+async fn update_handler(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    body: Json<Value>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let body = body.into_inner();
+    let has_status = body.get("status").is_some();
+    let mut collection = serde_json::from_value::<Collection>(body)
+        .map_err(|error| ApiError::BadRequest(format!("Invalid collection: {}", error)))?;
+    collection.id = id;
+    let (collection, ()) = edit_collection(&req, &state, &claims, id, Action::Update, |current| {
+        let requested = has_status.then_some(collection.status);
+        let stored = std::mem::replace(current, collection);
+        apply_status(&stored, current, requested)
+    })?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+/// Applies a JSON Patch (`application/json-patch+json`) or a JSON Merge Patch
+/// (`application/merge-patch+json` or plain `application/json`) to a collection.
+async fn patch_handler(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    patch: Json<Value>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let patch = patch.into_inner();
+    let is_json_patch = req.content_type() == JSON_PATCH_TYPE;
+    if !is_json_patch && !patch.is_object() {
+        return Err(ApiError::BadRequest(
+            "Merge patch body must be a JSON object.".to_string(),
+        ));
+    }
+    let (collection, ()) = edit_collection(&req, &state, &claims, id, Action::Update, |current| {
+        let mut value = serde_json::to_value(&*current)?;
+        if is_json_patch {
+            json_patch(&mut value, &patch).map_err(|error| match error {
+                PatchError::Invalid(message) => ApiError::BadRequest(message),
+                PatchError::Conflict(message) => ApiError::Conflict(message),
+            })?;
+        } else {
+            merge_patch(&mut value, &patch);
+        }
+        let patched = serde_json::from_value::<Collection>(value)
+            .map_err(|error| ApiError::BadRequest(format!("Invalid patch: {}", error)))?;
+        let requested = Some(patched.status);
+        let stored = std::mem::replace(current, patched);
+        current.id = id;
+        apply_status(&stored, current, requested)
+    })?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+/// Fails for the states only `POST /v1/projects/{id}/publish` may set, so a
+/// collection never goes public without `publish_at` being recorded.
+fn check_status(status: Status) -> std::result::Result<(), ApiError> {
+    match status {
+        Status::Draft | Status::Archived => Ok(()),
+        Status::Scheduled | Status::Published => Err(ApiError::Unprocessable(vec![FieldError {
+            field: "status".to_string(),
+            message: "Publish or schedule a collection with POST /v1/projects/{id}/publish."
+                .to_string(),
+        }])),
+    }
+}
+
+/// Carries the publishing state of `stored` over to its edited version, unless
+/// the edit asked for a different status it may set. Moving back to draft
+/// clears `publish_at`; clients never set it directly.
+fn apply_status(
+    stored: &Collection,
+    edited: &mut Collection,
+    requested: Option<Status>,
+) -> std::result::Result<(), ApiError> {
+    edited.status = stored.status;
+    edited.publish_at = stored.publish_at.clone();
+    let Some(status) = requested.filter(|status| *status != stored.status) else {
+        return Ok(());
+    };
+    check_status(status)?;
+    edited.status = status;
+    if status == Status::Draft {
+        edited.publish_at = None;
+    }
+    Ok(())
+}
+
+/// Stores a new version of `current` and records it in the history. Callers
+/// must hold the state lock.
+fn save_collection(
+    state: &AppState,
+    claims: &Claims,
+    action: Action,
+    current: &Collection,
+    mut collection: Collection,
+) -> std::result::Result<Collection, ApiError> {
+    let id = collection.id;
+    collection.position = current.position;
+    collection.last_modified = timestamp();
+    collection.revision = current.revision.saturating_add(1);
+    let collection = state
+        .store
+        .update(collection)?
+        .ok_or_else(|| not_found(id))?;
+    state.invalidate();
+    state.record(action, &claims.sub, Some(current), Some(&collection));
+    println!("Updated \"{}\"", collection.title);
+    Ok(collection)
+}
+
+/// Runs `edit` against the stored collection under the state lock, then validates
+/// and stores the result. Returns the stored collection and whatever `edit` returned.
+fn edit_collection<R>(
+    req: &HttpRequest,
+    state: &AppState,
+    claims: &Claims,
+    id: Uuid,
+    action: Action,
+    edit: impl FnOnce(&mut Collection) -> std::result::Result<R, ApiError>,
+) -> std::result::Result<(Collection, R), ApiError> {
+    let _guard = state.lock();
+    let current = state.store.get(id)?.ok_or_else(|| not_found(id))?;
+    check_if_match(req, &current)?;
+    let mut collection = current.clone();
+    let result = edit(&mut collection)?;
+    prepare(&mut collection)?;
+    let collection = save_collection(state, claims, action, &current, collection)?;
+    Ok((collection, result))
+}
+
+#[derive(Deserialize)]
+struct PublishQuery {
+    at: Option<String>,
+}
+
+/// Makes a collection public now or, when `at` is in the future, schedules it
+/// to go public then.
+async fn publish_handler(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    query: Query<PublishQuery>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let at = match &query.at {
+        Some(value) => Some(parse_timestamp(value).ok_or_else(|| {
+            ApiError::BadRequest(format!("at \"{}\" is not a valid time", value))
+        })?),
+        None => None,
+    };
+    let (collection, ()) =
+        edit_collection(&req, &state, &claims, id, Action::Publish, |current| {
+            if current.is_published() {
+                return Err(ApiError::Conflict(format!(
+                    "Collection {} is already published",
+                    id
+                )));
+            }
+            match at {
+                Some(at) if at > Utc::now() => {
+                    current.status = Status::Scheduled;
+                    current.publish_at = Some(at.format(TIMESTAMP_FORMAT).to_string());
+                }
+                _ => {
+                    current.status = Status::Published;
+                    current.publish_at = Some(timestamp());
                 }
             }
-            Err(error) => HttpResponse::from_error(error),
+            Ok(())
+        })?;
+    match &collection.publish_at {
+        Some(at) if collection.status == Status::Scheduled => {
+            println!("Scheduled \"{}\" for {}", collection.title, at)
+        }
+        _ => println!("Published \"{}\"", collection.title),
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+/// Lists a collection's recorded revisions, newest first. Snapshots are left out.
+async fn history_handler(id: Path<Uuid>, state: Data<AppState>) -> ApiResult {
+    let id = id.into_inner();
+    let mut revisions = state.history.list(id)?;
+    if revisions.is_empty() && state.get(id).is_none() {
+        return Err(not_found(id));
+    }
+    revisions.reverse();
+    for revision in revisions.iter_mut() {
+        revision.snapshot = None;
+    }
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+/// Restores a collection's content to an earlier revision, stored as a new revision.
+async fn revert_handler(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    path: Path<(Uuid, u64)>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let (id, target) = path.into_inner();
+    let snapshot = state
+        .history
+        .list(id)?
+        .into_iter()
+        .rev()
+        .find(|revision| revision.revision == target && revision.action != Action::Delete)
+        .and_then(|revision| revision.snapshot)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!("Collection {} has no revision {}", id, target))
+        })?;
+    let (collection, ()) = edit_collection(&req, &state, &claims, id, Action::Revert, |current| {
+        state.back_up("revert")?;
+        let stored = std::mem::replace(current, snapshot);
+        current.id = id;
+        // Reverting restores content; publishing state stays as it is.
+        apply_status(&stored, current, None)
+    })?;
+    println!("Reverted \"{}\" to revision {}", collection.title, target);
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+fn nested_not_found<T: Nested>(id: Uuid, item_id: u32) -> ApiError {
+    ApiError::NotFound(format!("Collection {} has no {} {}", id, T::NAME, item_id))
+}
+
+fn nested_position<T: Nested>(
+    collection: &mut Collection,
+    item_id: u32,
+) -> std::result::Result<usize, ApiError> {
+    let id = collection.id;
+    T::items(collection)
+        .iter()
+        .position(|item| item.id() == item_id)
+        .ok_or_else(|| nested_not_found::<T>(id, item_id))
+}
+
+async fn list_nested_handler<T: Nested>(
+    claims: Option<ReqData<Claims>>,
+    id: Path<Uuid>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let mut collection = find_visible(&state, &claims, id.into_inner())?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(T::items(&mut collection)))
+}
+
+async fn get_nested_handler<T: Nested>(
+    claims: Option<ReqData<Claims>>,
+    path: Path<(Uuid, u32)>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let (id, item_id) = path.into_inner();
+    let mut collection = find_visible(&state, &claims, id)?;
+    let index = nested_position::<T>(&mut collection, item_id)?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(&T::items(&mut collection)[index]))
+}
+
+/// Appends an item to a collection. The item's id is always assigned by the server.
+async fn create_nested_handler<T: Nested>(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    item: Json<T>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let mut item = item.into_inner();
+    let (collection, item) =
+        edit_collection(&req, &state, &claims, id, Action::Update, |collection| {
+            let items = T::items(collection);
+            let next_id = match items.iter().map(Nested::id).max() {
+                Some(max) => max.checked_add(1).ok_or_else(|| {
+                    ApiError::Conflict(format!("Collection {} has no free {} ids", id, T::NAME))
+                })?,
+                None => 0,
+            };
+            item.set_id(next_id);
+            let position = items
+                .iter()
+                .map(|item| item.position().saturating_add(1))
+                .max();
+            item.set_position(position.unwrap_or(0));
+            items.push(item.clone());
+            Ok(item)
+        })?;
+    Ok(HttpResponse::Created()
+        .insert_header((
+            header::LOCATION,
+            format!("/v1/projects/{}/{}/{}", id, T::PATH, item.id()),
+        ))
+        .insert_header(etag(&collection))
+        .json(item))
+}
+
+async fn update_nested_handler<T: Nested>(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    path: Path<(Uuid, u32)>,
+    item: Json<T>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let (id, item_id) = path.into_inner();
+    let mut item = item.into_inner();
+    item.set_id(item_id);
+    let (collection, item) =
+        edit_collection(&req, &state, &claims, id, Action::Update, |collection| {
+            let index = nested_position::<T>(collection, item_id)?;
+            let items = T::items(collection);
+            item.set_position(items[index].position());
+            items[index] = item.clone();
+            Ok(item)
+        })?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(item))
+}
+
+async fn del_nested_handler<T: Nested>(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    path: Path<(Uuid, u32)>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let (id, item_id) = path.into_inner();
+    let (collection, item) =
+        edit_collection(&req, &state, &claims, id, Action::Update, |collection| {
+            let index = nested_position::<T>(collection, item_id)?;
+            Ok(T::items(collection).remove(index))
+        })?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(item))
+}
+
+#[derive(Deserialize)]
+struct Reorder<I> {
+    ids: Vec<I>,
+}
+
+/// Puts a collection's items in the order given. Every existing id must be listed once.
+async fn reorder_nested_handler<T: Nested>(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    order: Json<Reorder<u32>>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let (mut collection, ()) =
+        edit_collection(&req, &state, &claims, id, Action::Update, |collection| {
+            let items = T::items(collection);
+            reorder(items, &order.ids, T::id, T::NAME)?;
+            for (item, position) in items.iter_mut().zip(0..) {
+                item.set_position(position);
+            }
+            Ok(())
+        })?;
+    Ok(HttpResponse::Ok()
+        .insert_header(etag(&collection))
+        .json(T::items(&mut collection)))
+}
+
+/// Sets the display order of every collection in one atomic write. Only
+/// collections whose position changes get a new revision.
+async fn reorder_handler(
+    claims: ReqData<Claims>,
+    order: Json<Reorder<Uuid>>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let _guard = state.lock();
+    let mut collections = state.store.list()?;
+    reorder(&mut collections, &order.ids, |item| item.id, "collection")?;
+    state.back_up("reorder")?;
+    let moved: Vec<(Collection, Collection)> = collections
+        .into_iter()
+        .zip(0..)
+        .filter(|(collection, position)| collection.position != *position)
+        .map(|(collection, position)| {
+            let mut moved = collection.clone();
+            moved.position = position;
+            (collection, moved)
+        })
+        .collect();
+    println!("Reordering collections ({} moved)", moved.len());
+    save_all(&state, &claims, Action::Reorder, moved)?;
+    Ok(HttpResponse::Ok().json(&*state.collections()))
+}
+
+/// Stores several changed collections in one atomic write, giving each a new
+/// revision. Takes pairs of the stored and the changed version. Callers must
+/// hold the state lock.
+fn save_all(
+    state: &AppState,
+    claims: &Claims,
+    action: Action,
+    changes: Vec<(Collection, Collection)>,
+) -> std::result::Result<(), ApiError> {
+    let now = timestamp();
+    let (before, after): (Vec<Collection>, Vec<Collection>) = changes
+        .into_iter()
+        .map(|(current, mut collection)| {
+            collection.last_modified = now.clone();
+            collection.revision = current.revision.saturating_add(1);
+            (current, collection)
+        })
+        .unzip();
+    state.store.update_all(after.clone())?;
+    state.invalidate();
+    for (current, collection) in before.iter().zip(&after) {
+        state.record(action, &claims.sub, Some(current), Some(collection));
+    }
+    Ok(())
+}
+
+/// Rearranges `items` to follow `ids`, which must list every item's id exactly once.
+fn reorder<T, I: PartialEq + Display>(
+    items: &mut Vec<T>,
+    ids: &[I],
+    id_of: impl Fn(&T) -> I,
+    name: &str,
+) -> std::result::Result<(), ApiError> {
+    let mut reordered = Vec::with_capacity(items.len());
+    for id in ids {
+        let Some(index) = items.iter().position(|item| id_of(item) == *id) else {
+            return Err(ApiError::BadRequest(format!(
+                "{} is not a {} id, or is listed twice",
+                id, name
+            )));
+        };
+        reordered.push(items.remove(index));
+    }
+    if !items.is_empty() {
+        return Err(ApiError::BadRequest(format!(
+            "The new order must list every {} id",
+            name
+        )));
+    }
+    *items = reordered;
+    Ok(())
+}
+
+async fn tags_handler(claims: Option<ReqData<Claims>>, state: Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(tag_counts(&visible_collections(&state, &claims)))
+}
+
+#[derive(Deserialize)]
+struct RenameTag {
+    from: String,
+    to: String,
+}
+
+#[derive(Deserialize)]
+struct MergeTags {
+    tags: Vec<String>,
+    into: String,
+}
+
+async fn rename_tag_handler(
+    claims: ReqData<Claims>,
+    rename: Json<RenameTag>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let RenameTag { from, to } = rename.into_inner();
+    retag_all(&state, &claims, &[from], &to)
+}
+
+async fn merge_tags_handler(
+    claims: ReqData<Claims>,
+    merge: Json<MergeTags>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let MergeTags { tags, into } = merge.into_inner();
+    retag_all(&state, &claims, &tags, &into)
+}
+
+/// Replaces the `sources` tags with `target` on every collection in one write
+/// and responds with the updated tag counts.
+fn retag_all(state: &AppState, claims: &Claims, sources: &[String], target: &str) -> ApiResult {
+    let target = normalize_tag(target);
+    if target.is_empty() {
+        return Err(ApiError::BadRequest(
+            "The new tag must not be empty.".to_string(),
+        ));
+    }
+    let _guard = state.lock();
+    let changed: Vec<(Collection, Collection)> = state
+        .store
+        .list()?
+        .into_iter()
+        .filter_map(|collection| {
+            let mut retagged = collection.clone();
+            retag(&mut retagged, sources, &target).then_some((collection, retagged))
+        })
+        .collect();
+    if changed.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No collection is tagged {}",
+            sources.join(", ")
+        )));
+    }
+    state.back_up("retag")?;
+    println!(
+        "Retagged {} collections: {} -> {}",
+        changed.len(),
+        sources.join(", "),
+        target
+    );
+    save_all(state, claims, Action::Retag, changed)?;
+    Ok(HttpResponse::Ok().json(tag_counts(&state.collections())))
+}
+
+/// Moves a collection to the trash, from which it can be restored until the
+/// retention period runs out.
+async fn del_handler(
+    req: HttpRequest,
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let _guard = state.lock();
+    let current = state.store.get(id)?.ok_or_else(|| not_found(id))?;
+    check_if_match(&req, &current)?;
+    state.back_up("delete")?;
+    state.trash.put(Deleted::new(current, &claims.sub))?;
+    let deleted = state.store.delete(id);
+    if !matches!(deleted, Ok(Some(_))) {
+        // Keep the collection in exactly one place.
+        if let Err(error) = state.trash.take(id) {
+            eprintln!("Failed to take {} back out of the trash: {}", id, error);
         }
+    }
+    let project = deleted?.ok_or_else(|| not_found(id))?;
+    state.invalidate();
+    state.record(Action::Delete, &claims.sub, Some(&project), None);
+    println!("{} moved to the trash", project.title);
+    Ok(HttpResponse::Ok().json(project))
+}
+
+/// Lists deleted collections, most recently deleted first.
+async fn trash_handler(state: Data<AppState>) -> ApiResult {
+    let mut deleted = state.trash.list()?;
+    deleted.reverse();
+    Ok(HttpResponse::Ok().json(deleted))
+}
+
+/// Puts a deleted collection back, at the end of the display order.
+async fn restore_handler(
+    claims: ReqData<Claims>,
+    id: Path<Uuid>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let id = id.into_inner();
+    let _guard = state.lock();
+    let deleted = state
+        .trash
+        .get(id)?
+        .ok_or_else(|| ApiError::NotFound(format!("Collection {} is not in the trash", id)))?;
+    let mut collection = deleted.collection;
+    collection.position = next_position(&state.store.list()?);
+    collection.last_modified = timestamp();
+    collection.revision = collection.revision.saturating_add(1);
+    let collection = state.store.insert(collection)?;
+    if let Err(error) = state.trash.take(id) {
+        eprintln!("Failed to remove restored {} from the trash: {}", id, error);
+    }
+    state.invalidate();
+    state.record(Action::Restore, &claims.sub, None, Some(&collection));
+    println!("Restored \"{}\" from the trash", collection.title);
+    Ok(HttpResponse::Ok()
+        .insert_header((header::LOCATION, format!("/v1/projects/{}", collection.id)))
+        .insert_header(etag(&collection))
+        .json(collection))
+}
+
+#[derive(Serialize)]
+struct BackupListing {
+    #[serde(flatten)]
+    snapshot: Snapshot,
+    /// `None` if the snapshot cannot be read or fails its checksum.
+    collections: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct RestoredBackup {
+    restored: String,
+    /// Snapshot of the data as it was just before the restore.
+    safety_backup: String,
+    collections: usize,
+}
+
+fn find_backup(state: &AppState, name: &str) -> std::result::Result<Snapshot, ApiError> {
+    state
+        .backups
+        .get(name)?
+        .ok_or_else(|| ApiError::NotFound(format!("No backup named {}", name)))
+}
+
+/// A damaged snapshot is the client's choice of backup, not a server fault.
+fn backup_error(error: Error) -> ApiError {
+    if error.kind() == ErrorKind::InvalidData {
+        ApiError::Conflict(error.to_string())
     } else {
-        HttpResponse::Unauthorized().body("Unauthorized token.")
-    }
-}
-
-async fn del_handler(project: Json<Collection>, remote_key: BearerAuth) -> HttpResponse {
-    if let Ok(_) = check_auth(remote_key.token().to_string()) {
-        let local_projects_path = format!("{}/{}.json", LOCAL_PROJECTS_PATH, PROJECTS_FILE_NAME);
-        let mut projects = load_from_storage(&local_projects_path).unwrap();
-        let project_id: usize = project.id.try_into().unwrap();
-        projects.remove(project_id);
-        let mut i: u32 = 0;
-        let projects = projects
-            .into_iter()
-            .map(|mut item| {
-                item.id = i;
-                i += 1;
-                item
-            })
-            .collect();
-        match write_local_db(&local_projects_path, projects) {
-            Ok(_) => {
-                println!("{} deleted!", project.title);
-                HttpResponse::Ok().body("test")
+        error.into()
+    }
+}
+
+/// Lists backup snapshots, newest first, with the number of collections in each.
+async fn backups_handler(state: Data<AppState>) -> ApiResult {
+    let backups: Vec<BackupListing> = state
+        .backups
+        .list()?
+        .into_iter()
+        .map(|snapshot| {
+            let collections = state.backups.load(&snapshot).ok().map(|items| items.len());
+            BackupListing {
+                snapshot,
+                collections,
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(backups))
+}
+
+/// Sends a snapshot file exactly as stored.
+async fn download_backup_handler(name: Path<String>, state: Data<AppState>) -> ApiResult {
+    let snapshot = find_backup(&state, &name)?;
+    let bytes = state.backups.read(&snapshot).map_err(backup_error)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(snapshot.name)],
+        })
+        .body(bytes))
+}
+
+/// Replaces the live collections with a snapshot's, after snapshotting the
+/// current state. Collections the restore changes get a new revision, so ETags
+/// handed out before it no longer match.
+async fn restore_backup_handler(
+    claims: ReqData<Claims>,
+    name: Path<String>,
+    state: Data<AppState>,
+) -> ApiResult {
+    let _guard = state.lock();
+    let snapshot = find_backup(&state, &name)?;
+    let restored = state.backups.load(&snapshot).map_err(backup_error)?;
+    let safety_backup = state.back_up(PRE_RESTORE)?;
+    let current = state.store.list()?;
+    let now = timestamp();
+    let mut changes: Vec<(Option<Collection>, Collection)> = Vec::new();
+    let restored: Vec<Collection> = restored
+        .into_iter()
+        .map(|mut collection| {
+            let existing = current.iter().find(|item| item.id == collection.id);
+            if existing == Some(&collection) {
+                return collection;
             }
-            Err(error) => {
-                eprintln!("Failed to delete project: {}", error);
-                HttpResponse::from_error(error)
+            let revision = existing.map_or(0, |item| item.revision);
+            collection.revision = revision.max(collection.revision).saturating_add(1);
+            collection.last_modified = now.clone();
+            changes.push((existing.cloned(), collection.clone()));
+            collection
+        })
+        .collect();
+    let removed: Vec<&Collection> = current
+        .iter()
+        .filter(|item| !restored.iter().any(|collection| collection.id == item.id))
+        .collect();
+    // Collections missing from the backup go to the trash like any delete.
+    for collection in &removed {
+        state
+            .trash
+            .put(Deleted::new((*collection).clone(), &claims.sub))?;
+    }
+    let count = restored.len();
+    let restored_ids: Vec<Uuid> = restored.iter().map(|collection| collection.id).collect();
+    if let Err(error) = state.store.replace_all(restored) {
+        for collection in &removed {
+            if let Err(error) = state.trash.take(collection.id) {
+                eprintln!(
+                    "Failed to take {} back out of the trash: {}",
+                    collection.id, error
+                );
             }
         }
+        return Err(error.into());
+    }
+    // Restored collections are live again, so they must not stay in the trash.
+    for id in restored_ids {
+        if let Err(error) = state.trash.take(id) {
+            eprintln!("Failed to take {} out of the trash: {}", id, error);
+        }
+    }
+    state.invalidate();
+    for (before, after) in &changes {
+        state.record(Action::Restore, &claims.sub, before.as_ref(), Some(after));
+    }
+    for collection in removed {
+        state.record(Action::Delete, &claims.sub, Some(collection), None);
+    }
+    println!(
+        "Restored backup \"{}\" ({} collections)",
+        snapshot.name, count
+    );
+    Ok(HttpResponse::Ok().json(RestoredBackup {
+        restored: snapshot.name,
+        safety_backup: safety_backup.name,
+        collections: count,
+    }))
+}
+
+#[derive(Deserialize)]
+struct LoginRequest {
+    key: String,
+}
+
+/// Exchanges an API key for a short-lived session token.
+async fn login_handler(credentials: Json<LoginRequest>, sessions: Data<Sessions>) -> ApiResult {
+    let session = sessions
+        .login(&credentials.key)
+        .map_err(|error| key_store_error(error, "Unknown API key."))?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// Issues a new token for a valid session whose API key still exists with the
+/// same role.
+async fn refresh_handler(claims: ReqData<Claims>, sessions: Data<Sessions>) -> ApiResult {
+    let session = sessions.refresh(&claims).map_err(|error| {
+        key_store_error(error, "The session's API key has been revoked or changed.")
+    })?;
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// `denied` for keys that are not accepted; any other error means the key
+/// store itself could not be read.
+fn key_store_error(error: Error, denied: &str) -> ApiError {
+    if error.kind() == ErrorKind::PermissionDenied {
+        ApiError::Unauthorized(denied.to_string())
     } else {
-        HttpResponse::Unauthorized().body("Unauthorized token.")
+        eprintln!("Key store unavailable: {}", error);
+        ApiError::Unavailable("Authentication is temporarily unavailable.".to_string())
     }
 }
 
+async fn logout_handler(claims: ReqData<Claims>, sessions: Data<Sessions>) -> HttpResponse {
+    sessions.revoke(&claims);
+    println!("Session for \"{}\" ended", claims.sub);
+    HttpResponse::NoContent().finish()
+}
+
 async fn status_handler() -> HttpResponse {
     HttpResponse::Ok().body("folio is running")
 }
+
+async fn method_not_allowed_handler(req: HttpRequest) -> ApiResult {
+    Err(ApiError::MethodNotAllowed(format!(
+        "{} is not supported on {}",
+        req.method(),
+        req.path()
+    )))
+}
+
+async fn not_found_handler(req: HttpRequest) -> ApiResult {
+    Err(ApiError::NotFound(format!(
+        "No route for {} {}",
+        req.method(),
+        req.path()
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        body::to_bytes,
+        dev::{Service, ServiceResponse},
+        http::{header, header::HeaderMap, Method, StatusCode},
+        test::{self, TestRequest},
+        web::Data,
+        App,
+    };
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    use super::configure;
+    use crate::{
+        auth::{
+            keys::{KeyStore, Role},
+            session::Sessions,
+        },
+        core::{
+            data::{Collection, Status},
+            utils::TempDir,
+        },
+        state::AppState,
+    };
+
+    struct Fixture {
+        state: Data<AppState>,
+        sessions: Data<Sessions>,
+        keys_path: String,
+        collection: Collection,
+        _dir: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let dir = TempDir::create();
+            let mut collection = Collection::default(Vec::new());
+            collection.status = Status::Published;
+            let state = AppState::in_temp_dir(&dir, vec![collection.clone()]);
+            let keys_path = dir.path("keys.json");
+            Fixture {
+                state: Data::new(state),
+                sessions: Data::new(Sessions::new(&keys_path, 15).unwrap()),
+                keys_path,
+                collection,
+                _dir: dir,
+            }
+        }
+
+        /// A session for the key named after `role`, added on first use.
+        fn token(&self, role: Role) -> String {
+            let mut keys = KeyStore::load(&self.keys_path).unwrap();
+            if !keys.keys().iter().any(|key| key.name == role.as_str()) {
+                keys.add(role.as_str(), role).unwrap();
+            }
+            let key = keys
+                .keys()
+                .iter()
+                .find(|key| key.name == role.as_str())
+                .unwrap();
+            format!("Bearer {}", self.sessions.issue(key).unwrap().token)
+        }
+    }
+
+    /// Sends `req` and returns the status and JSON body of the response.
+    async fn send(fixture: &Fixture, req: TestRequest) -> (StatusCode, Value) {
+        let (status, _, body) = exchange(fixture, req).await;
+        (status, body)
+    }
+
+    /// Like `send`, but also returns the response headers.
+    async fn exchange(fixture: &Fixture, req: TestRequest) -> (StatusCode, HeaderMap, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(fixture.state.clone())
+                .app_data(fixture.sessions.clone())
+                .configure(configure),
+        )
+        .await;
+        let response: ServiceResponse = match app.call(req.to_request()).await {
+            Ok(response) => response,
+            Err(error) => ServiceResponse::new(
+                TestRequest::default().to_http_request(),
+                error.error_response(),
+            ),
+        };
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (
+            status,
+            headers,
+            serde_json::from_slice(&body).unwrap_or(Value::Null),
+        )
+    }
+
+    /// Sends `req` and checks that it fails with `status` and a structured error body.
+    async fn assert_error(fixture: &Fixture, req: TestRequest, status: StatusCode, code: &str) {
+        let (actual, body) = send(fixture, req).await;
+        assert_eq!(actual, status, "unexpected body {}", body);
+        assert_eq!(body["code"], code, "unexpected body {}", body);
+        assert!(body["message"].is_string());
+    }
+
+    fn project(fixture: &Fixture) -> String {
+        format!("/v1/projects/{}", fixture.collection.id)
+    }
+
+    #[actix_web::test]
+    async fn rejects_malformed_paths_and_unknown_routes() {
+        let fixture = Fixture::new();
+        let admin = fixture.token(Role::Admin);
+        for (method, uri) in [
+            (Method::GET, "/v1/projects/not-a-uuid"),
+            (Method::GET, "/v1/projects/123"),
+            (Method::PUT, "/v1/projects/%00"),
+            (
+                Method::DELETE,
+                "/v1/projects/00000000-0000-0000-0000-00000000000Z",
+            ),
+        ] {
+            let req = TestRequest::default()
+                .method(method)
+                .uri(uri)
+                .insert_header((header::AUTHORIZATION, admin.clone()))
+                .set_json(&fixture.collection);
+            assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+        }
+        let req = TestRequest::get().uri("/v2/projects");
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        let req = TestRequest::post().uri("/v1/folio");
+        assert_error(
+            &fixture,
+            req,
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_list_queries() {
+        let fixture = Fixture::new();
+        for query in [
+            "limit=abc",
+            "limit=0",
+            "limit=-1",
+            "limit=1000",
+            "featured=maybe",
+            "sort=summary",
+            "cursor=%%%",
+            "modified_since=soon",
+        ] {
+            let req = TestRequest::get().uri(&format!("/v1/projects?{}", query));
+            assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+        }
+        for query in ["", "q=", "q=%20%20", "q=a&limit=0", "q=a&limit=x"] {
+            let req = TestRequest::get().uri(&format!("/v1/search?{}", query));
+            assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_unknown_ids() {
+        let fixture = Fixture::new();
+        let admin = fixture.token(Role::Admin);
+        let uri = format!("/v1/projects/{}", Uuid::new_v4());
+        for method in [Method::GET, Method::PUT, Method::PATCH, Method::DELETE] {
+            let req = TestRequest::default()
+                .method(method)
+                .uri(&uri)
+                .insert_header((header::AUTHORIZATION, admin.clone()))
+                .set_json(&fixture.collection);
+            assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn rejects_malformed_bodies() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let auth = (header::AUTHORIZATION, editor);
+        let json = (header::CONTENT_TYPE, "application/json");
+
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header(auth.clone())
+            .insert_header(json.clone())
+            .set_payload("{\"title\": ");
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+
+        let req = TestRequest::put()
+            .uri(&project(&fixture))
+            .insert_header(auth.clone())
+            .set_json(json!({ "title": 42 }));
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+
+        for patch in [
+            json!([1, 2]),
+            json!("title"),
+            json!({ "keypoints": "none" }),
+        ] {
+            let req = TestRequest::patch()
+                .uri(&project(&fixture))
+                .insert_header(auth.clone())
+                .set_json(patch);
+            assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+        }
+
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header(auth.clone())
+            .insert_header((header::CONTENT_TYPE, "text/plain"))
+            .set_payload(serde_json::to_vec(&fixture.collection).unwrap());
+        assert_error(
+            &fixture,
+            req,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header(auth)
+            .insert_header(json)
+            .set_payload(vec![b' '; 4 * 1024 * 1024]);
+        assert_error(
+            &fixture,
+            req,
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/v1/auth/login")
+            .set_json(json!({ "secret": "folio_" }));
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_patches_that_do_not_apply() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let json_patch = (header::CONTENT_TYPE, "application/json-patch+json");
+        for (patch, status, code) in [
+            (
+                json!({ "op": "remove", "path": "/title" }),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+            (
+                json!([{ "op": "remove", "path": "/keypoints/7" }]),
+                StatusCode::CONFLICT,
+                "conflict",
+            ),
+            (
+                json!([{ "op": "test", "path": "/title", "value": "Other" }]),
+                StatusCode::CONFLICT,
+                "conflict",
+            ),
+            (
+                json!([{ "op": "remove", "path": "/title" }]),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+            (
+                json!([{ "op": "add", "path": "/keypoints/-", "value": fixture.collection.keypoints[0] }]),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+            ),
+        ] {
+            let req = TestRequest::patch()
+                .uri(&project(&fixture))
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_payload(patch.to_string())
+                .insert_header(json_patch.clone());
+            assert_error(&fixture, req, status, code).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn manages_nested_items() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let keypoints = format!("{}/keypoints", project(&fixture));
+        let keypoint = json!({ "id": 0, "featured": [], "title": "Second", "summary": "" });
+
+        let req = TestRequest::post()
+            .uri(&keypoints)
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&keypoint);
+        let (status, created) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["id"], 1);
+
+        let req = TestRequest::post()
+            .uri(&format!("{}/reorder", keypoints))
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(json!({ "ids": [1, 0] }));
+        let (status, reordered) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(reordered[0]["title"], "Second");
+
+        let req = TestRequest::delete()
+            .uri(&format!("{}/0", keypoints))
+            .insert_header((header::AUTHORIZATION, editor.clone()));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+        let collection = fixture.state.get(fixture.collection.id).unwrap();
+        assert_eq!(collection.keypoints.len(), 1);
+        assert_eq!(collection.revision, fixture.collection.revision + 3);
+
+        for (req, status, code) in [
+            (
+                TestRequest::put().uri(&format!("{}/0", keypoints)),
+                StatusCode::NOT_FOUND,
+                "not_found",
+            ),
+            (
+                TestRequest::post().uri(&format!("{}/reorder", keypoints)),
+                StatusCode::BAD_REQUEST,
+                "bad_request",
+            ),
+        ] {
+            let req = req
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_json(json!({ "ids": [1, 1], "featured": [], "title": "x", "summary": "" }));
+            assert_error(&fixture, req, status, code).await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn reorders_collections() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let mut collection = fixture.collection.clone();
+        collection.id = Uuid::nil();
+        collection.status = Status::Draft;
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&collection);
+        let (status, created) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["position"], 1);
+
+        let first = fixture.collection.id;
+        let second: Uuid = serde_json::from_value(created["id"].clone()).unwrap();
+        let req = TestRequest::post()
+            .uri("/v1/projects/reorder")
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(json!({ "ids": [second, first] }));
+        let (status, listed) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(listed[0]["id"], json!(second));
+        assert_eq!(listed[0]["position"], 0);
+        assert_eq!(listed[1]["position"], 1);
+
+        for ids in [json!([second]), json!([second, first, first]), json!([1])] {
+            let req = TestRequest::post()
+                .uri("/v1/projects/reorder")
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_json(json!({ "ids": ids }));
+            assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+        }
+    }
+
+    #[actix_web::test]
+    async fn records_history_and_reverts() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        for title in ["Second", "Third"] {
+            let mut collection = fixture.collection.clone();
+            collection.title = title.to_string();
+            let req = TestRequest::put()
+                .uri(&project(&fixture))
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_json(&collection);
+            assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+        }
+
+        let req = TestRequest::post()
+            .uri(&format!("{}/revert/2", project(&fixture)))
+            .insert_header((header::AUTHORIZATION, editor.clone()));
+        let (status, reverted) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            (&reverted["title"], &reverted["revision"]),
+            (&json!("Second"), &json!(4))
+        );
+
+        let req = TestRequest::get()
+            .uri(&format!("{}/history", project(&fixture)))
+            .insert_header((header::AUTHORIZATION, editor.clone()));
+        let (status, history) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(history[0]["action"], "revert");
+        assert_eq!(history[0]["author"], "editor");
+        let diff = history[0]["diff"].as_array().unwrap();
+        assert!(diff.iter().any(|operation| operation["path"] == "/title"));
+        assert_eq!(history.as_array().unwrap().len(), 3);
+
+        let req = TestRequest::post()
+            .uri(&format!("{}/revert/99", project(&fixture)))
+            .insert_header((header::AUTHORIZATION, editor));
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+    }
+
+    #[actix_web::test]
+    async fn restores_deleted_collections_from_trash() {
+        let fixture = Fixture::new();
+        let admin = fixture.token(Role::Admin);
+        let req = TestRequest::delete()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+        let req = TestRequest::get().uri(&project(&fixture));
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+
+        let req = TestRequest::get()
+            .uri("/v1/trash")
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, trash) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(trash[0]["collection"]["id"], json!(fixture.collection.id));
+        assert_eq!(trash[0]["deleted_by"], "admin");
+
+        let restore = format!("/v1/trash/{}/restore", fixture.collection.id);
+        let req = TestRequest::post()
+            .uri(&restore)
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, restored) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(restored["revision"], json!(fixture.collection.revision + 1));
+        let req = TestRequest::get().uri(&project(&fixture));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+
+        let req = TestRequest::post()
+            .uri(&restore)
+            .insert_header((header::AUTHORIZATION, admin));
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+    }
+
+    #[actix_web::test]
+    async fn lists_downloads_and_restores_backups() {
+        let fixture = Fixture::new();
+        let admin = fixture.token(Role::Admin);
+        let req = TestRequest::delete()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+
+        let req = TestRequest::get()
+            .uri("/v1/backups")
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, backups) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(backups[0]["reason"], "delete");
+        assert_eq!(backups[0]["collections"], 1);
+        let name = backups[0]["name"].as_str().unwrap().to_string();
+        let restore = format!("/v1/backups/{}/restore", name);
+
+        let req = TestRequest::get()
+            .uri(&format!("/v1/backups/{}", name))
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, body) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["id"], json!(fixture.collection.id));
+
+        let req = TestRequest::post()
+            .uri(&restore)
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, body) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["collections"], 1);
+        assert!(body["safety_backup"]
+            .as_str()
+            .unwrap()
+            .ends_with("-pre-restore.json"));
+        let req = TestRequest::get().uri(&project(&fixture));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+        let req = TestRequest::get()
+            .uri("/v1/trash")
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        assert_eq!(send(&fixture, req).await, (StatusCode::OK, json!([])));
+
+        // Collections the backup does not have end up in the trash.
+        let mut collection = fixture.collection.clone();
+        collection.id = Uuid::nil();
+        collection.status = Status::Draft;
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, admin.clone()))
+            .set_json(&collection);
+        let (status, created) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let req = TestRequest::post()
+            .uri(&restore)
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+        let req = TestRequest::get()
+            .uri("/v1/trash")
+            .insert_header((header::AUTHORIZATION, admin.clone()));
+        let (status, trash) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(trash
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|deleted| deleted["collection"]["id"] == created["id"]));
+        let pre_restore = fixture.state.backups.list().unwrap();
+        let pre_restore = pre_restore
+            .iter()
+            .filter(|snapshot| snapshot.reason == "pre-restore");
+        assert_eq!(pre_restore.count(), 2);
+
+        let req = TestRequest::post()
+            .uri("/v1/backups/projects.json/restore")
+            .insert_header((header::AUTHORIZATION, admin));
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        let req = TestRequest::get()
+            .uri("/v1/backups")
+            .insert_header((header::AUTHORIZATION, fixture.token(Role::Editor)));
+        assert_error(&fixture, req, StatusCode::FORBIDDEN, "forbidden").await;
+    }
+
+    #[actix_web::test]
+    async fn hides_drafts_until_published() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let req = TestRequest::patch()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(json!({ "status": "draft" }));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+
+        let req = TestRequest::get().uri(&project(&fixture));
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        for uri in ["/v1/projects", "/v1/search?q=title", "/v1/tags"] {
+            let (status, body) = send(&fixture, TestRequest::get().uri(uri)).await;
+            assert_eq!((status, body), (StatusCode::OK, json!([])), "{}", uri);
+            let req = TestRequest::get()
+                .uri(uri)
+                .insert_header((header::AUTHORIZATION, editor.clone()));
+            let (status, body) = send(&fixture, req).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(body.as_array().unwrap().len(), 1, "{}", uri);
+        }
+
+        let publish = format!("{}/publish", project(&fixture));
+        let req = TestRequest::post().uri(&format!("{}?at=2999-01-01", publish));
+        assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+        let req = TestRequest::post()
+            .uri(&format!("{}?at=2999-01-01", publish))
+            .insert_header((header::AUTHORIZATION, editor.clone()));
+        let (status, scheduled) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(scheduled["status"], "scheduled");
+        assert!(fixture.state.publish_due().unwrap().is_empty());
+
+        let req = TestRequest::post()
+            .uri(&publish)
+            .insert_header((header::AUTHORIZATION, editor.clone()));
+        let (status, published) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(published["status"], "published");
+        let req = TestRequest::get().uri(&project(&fixture));
+        assert_eq!(send(&fixture, req).await.0, StatusCode::OK);
+
+        let req = TestRequest::post()
+            .uri(&publish)
+            .insert_header((header::AUTHORIZATION, editor));
+        assert_error(&fixture, req, StatusCode::CONFLICT, "conflict").await;
+    }
+
+    #[actix_web::test]
+    async fn publishes_only_through_the_publish_endpoint() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let mut collection = json!(fixture.collection);
+        collection.as_object_mut().unwrap().remove("id");
+        collection.as_object_mut().unwrap().remove("status");
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&collection);
+        let (status, created) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["status"], "draft");
+        let draft = format!("/v1/projects/{}", created["id"].as_str().unwrap());
+
+        // A full replacement without a status keeps the draft private.
+        collection["title"] = json!("Renamed");
+        let req = TestRequest::put()
+            .uri(&draft)
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&collection);
+        let (status, updated) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            (updated["title"].as_str(), updated["status"].as_str()),
+            (Some("Renamed"), Some("draft"))
+        );
+        assert_error(
+            &fixture,
+            TestRequest::get().uri(&draft),
+            StatusCode::NOT_FOUND,
+            "not_found",
+        )
+        .await;
+
+        collection["status"] = json!("published");
+        for req in [
+            TestRequest::post().uri("/v1/projects"),
+            TestRequest::put().uri(&draft),
+            TestRequest::patch().uri(&draft),
+        ] {
+            let req = req
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_json(&collection);
+            assert_error(
+                &fixture,
+                req,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+            )
+            .await;
+        }
+        let req = TestRequest::patch()
+            .uri(&draft)
+            .insert_header((header::AUTHORIZATION, editor))
+            .set_json(json!({ "status": "scheduled", "publish_at": "2000-01-01" }));
+        assert_error(
+            &fixture,
+            req,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        )
+        .await;
+        assert!(fixture.state.publish_due().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn rejects_client_assigned_ids() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&fixture.collection);
+        assert_error(&fixture, req, StatusCode::CONFLICT, "conflict").await;
+
+        let mut collection = fixture.collection.clone();
+        collection.id = Uuid::new_v4();
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, editor))
+            .set_json(&collection);
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_collections() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let mut collection = fixture.collection.clone();
+        collection.accent_color = "red".to_string();
+        for req in [
+            TestRequest::post().uri("/v1/projects"),
+            TestRequest::put().uri(&project(&fixture)),
+        ] {
+            let mut collection = collection.clone();
+            collection.id = Default::default();
+            let req = req
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .set_json(&collection);
+            assert_error(
+                &fixture,
+                req,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "validation_failed",
+            )
+            .await;
+        }
+        let req = TestRequest::patch()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, editor))
+            .set_json(json!({ "title": "" }));
+        assert_error(
+            &fixture,
+            req,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "validation_failed",
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_missing_invalid_and_underprivileged_tokens() {
+        let fixture = Fixture::new();
+        let req = TestRequest::delete().uri(&project(&fixture));
+        assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+
+        for token in ["Bearer", "Bearer a.b.c", "Bearer ....", "Basic Zm9saW86"] {
+            let req = TestRequest::delete()
+                .uri(&project(&fixture))
+                .insert_header((header::AUTHORIZATION, token));
+            assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+        }
+
+        let req = TestRequest::delete()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, fixture.token(Role::Editor)));
+        assert_error(&fixture, req, StatusCode::FORBIDDEN, "forbidden").await;
+
+        let req = TestRequest::put()
+            .uri(&project(&fixture))
+            .insert_header((header::AUTHORIZATION, fixture.token(Role::ReadOnly)))
+            .set_json(&fixture.collection);
+        assert_error(&fixture, req, StatusCode::FORBIDDEN, "forbidden").await;
+    }
+
+    #[actix_web::test]
+    async fn renames_and_merges_tags() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let rename = |body: Value, token: String| {
+            TestRequest::post()
+                .uri("/v1/tags/rename")
+                .insert_header((header::AUTHORIZATION, token))
+                .set_json(body)
+        };
+        let req = rename(
+            json!({ "from": "default", "to": "featured" }),
+            fixture.token(Role::ReadOnly),
+        );
+        assert_error(&fixture, req, StatusCode::FORBIDDEN, "forbidden").await;
+        let req = rename(
+            json!({ "from": "missing", "to": "featured" }),
+            editor.clone(),
+        );
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        let req = rename(json!({ "from": "default", "to": " . " }), editor.clone());
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+
+        let req = rename(
+            json!({ "from": "Default", "to": "Featured" }),
+            editor.clone(),
+        );
+        let (status, tags) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tags, json!([{ "tag": "featured", "count": 1 }]));
+        let first = fixture
+            .state
+            .store
+            .get(fixture.collection.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.tags, ["featured"]);
+        assert_eq!(first.revision, fixture.collection.revision + 1);
+
+        let mut collection = fixture.collection.clone();
+        collection.id = Uuid::nil();
+        collection.status = Status::Draft;
+        collection.tags = vec!["Print".to_string()];
+        let req = TestRequest::post()
+            .uri("/v1/projects")
+            .insert_header((header::AUTHORIZATION, editor.clone()))
+            .set_json(&collection);
+        let (status, created) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let second: Uuid = serde_json::from_value(created["id"].clone()).unwrap();
+
+        let merge = |body: Value, token: String| {
+            TestRequest::post()
+                .uri("/v1/tags/merge")
+                .insert_header((header::AUTHORIZATION, token))
+                .set_json(body)
+        };
+        let req = merge(
+            json!({ "tags": ["featured"], "into": "work" }),
+            fixture.token(Role::ReadOnly),
+        );
+        assert_error(&fixture, req, StatusCode::FORBIDDEN, "forbidden").await;
+        let req = merge(
+            json!({ "tags": ["missing"], "into": "work" }),
+            editor.clone(),
+        );
+        assert_error(&fixture, req, StatusCode::NOT_FOUND, "not_found").await;
+        let req = merge(json!({ "tags": ["featured"], "into": "" }), editor.clone());
+        assert_error(&fixture, req, StatusCode::BAD_REQUEST, "bad_request").await;
+
+        let req = merge(
+            json!({ "tags": ["featured", "print"], "into": "Work" }),
+            editor,
+        );
+        let (status, tags) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(tags, json!([{ "tag": "work", "count": 2 }]));
+        for (id, revision) in [(fixture.collection.id, first.revision + 1), (second, 2)] {
+            let collection = fixture.state.store.get(id).unwrap().unwrap();
+            assert_eq!(collection.tags, ["work"]);
+            assert_eq!(collection.revision, revision);
+        }
+    }
+
+    #[actix_web::test]
+    async fn refreshes_sessions_only_while_their_key_exists() {
+        let fixture = Fixture::new();
+        let refresh = |token: String| {
+            TestRequest::post()
+                .uri("/v1/auth/refresh")
+                .insert_header((header::AUTHORIZATION, token))
+        };
+        let (status, session) = send(&fixture, refresh(fixture.token(Role::Editor))).await;
+        assert_eq!(status, StatusCode::OK);
+        let token = format!("Bearer {}", session["token"].as_str().unwrap());
+
+        let mut keys = KeyStore::load(&fixture.keys_path).unwrap();
+        keys.rotate(Role::Editor.as_str()).unwrap();
+        let req = refresh(token);
+        assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+
+        let token = fixture.token(Role::Editor);
+        keys.revoke(Role::Editor.as_str()).unwrap();
+        let req = refresh(token.clone());
+        assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+
+        // A key re-added under the same name does not count, whatever its role.
+        for role in [Role::Editor, Role::Admin] {
+            keys.add(Role::Editor.as_str(), role).unwrap();
+            let req = refresh(token.clone());
+            assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+            keys.revoke(Role::Editor.as_str()).unwrap();
+        }
+    }
+
+    #[actix_web::test]
+    async fn opens_sessions_for_known_keys() {
+        let fixture = Fixture::new();
+        let mut keys = KeyStore::load(&fixture.keys_path).unwrap();
+        let secret = keys.add("writer", Role::Editor).unwrap();
+        let login = |key: &str| {
+            TestRequest::post()
+                .uri("/v1/auth/login")
+                .set_json(json!({ "key": key }))
+        };
+        let req = login("folio_unknown");
+        assert_error(&fixture, req, StatusCode::UNAUTHORIZED, "unauthorized").await;
+
+        let (status, session) = send(&fixture, login(&secret)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(session["token_type"], "Bearer");
+        let req = TestRequest::patch()
+            .uri(&project(&fixture))
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", session["token"].as_str().unwrap()),
+            ))
+            .set_json(json!({ "title": "Logged in" }));
+        let (status, updated) = send(&fixture, req).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["title"], "Logged in");
+    }
+
+    #[actix_web::test]
+    async fn accepts_matching_preconditions() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        let (status, headers, _) =
+            exchange(&fixture, TestRequest::get().uri(&project(&fixture))).await;
+        assert_eq!(status, StatusCode::OK);
+        let etag = headers
+            .get(header::ETAG)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(etag, format!("\"{}\"", fixture.collection.revision));
+
+        let put = || {
+            TestRequest::put()
+                .uri(&project(&fixture))
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .insert_header((header::IF_MATCH, etag.clone()))
+                .set_json(&fixture.collection)
+        };
+        let (status, headers, updated) = exchange(&fixture, put()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(updated["revision"], json!(fixture.collection.revision + 1));
+        assert_eq!(
+            headers.get(header::ETAG).unwrap().to_str().unwrap(),
+            format!("\"{}\"", fixture.collection.revision + 1)
+        );
+
+        // The old ETag is stale once the update went through.
+        assert_error(
+            &fixture,
+            put(),
+            StatusCode::PRECONDITION_FAILED,
+            "precondition_failed",
+        )
+        .await;
+    }
+
+    #[actix_web::test]
+    async fn rejects_stale_and_malformed_preconditions() {
+        let fixture = Fixture::new();
+        let editor = fixture.token(Role::Editor);
+        for if_match in [
+            "\"0\"",
+            "\"18446744073709551615\"",
+            "W/\"1\"",
+            "not an etag",
+        ] {
+            let req = TestRequest::put()
+                .uri(&project(&fixture))
+                .insert_header((header::AUTHORIZATION, editor.clone()))
+                .insert_header((header::IF_MATCH, if_match))
+                .set_json(&fixture.collection);
+            assert_error(
+                &fixture,
+                req,
+                StatusCode::PRECONDITION_FAILED,
+                "precondition_failed",
+            )
+            .await;
+        }
+    }
+}
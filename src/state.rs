@@ -0,0 +1,345 @@
+use std::{
+    io::Result,
+    sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::{rt, web::Data};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::core::{
+    backup::{Backups, Snapshot, SCHEDULED},
+    data::{Collection, Status},
+    history::{Action, History, Revision},
+    search::{Hit, SearchIndex},
+    store::Store,
+    trash::Trash,
+    utils::{parse_timestamp, timestamp},
+};
+
+/// How often the store is checked for changes made outside the server.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+/// How often scheduled collections are checked for being due.
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+/// Author recorded in the history for changes nobody asked for directly.
+const SCHEDULER: &str = "scheduler";
+/// How often collections past the trash retention period are purged.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// State shared by every server worker.
+///
+/// Reads are served from an in-memory copy of the store and a search index over
+/// it, both rebuilt after each mutation and whenever the store's backing file
+/// changes on disk.
+pub struct AppState {
+    pub store: Box<dyn Store>,
+    pub history: History,
+    pub trash: Trash,
+    pub backups: Backups,
+    write_lock: Mutex<()>,
+    collections: RwLock<Vec<Collection>>,
+    search_index: RwLock<SearchIndex>,
+    loaded_at: Mutex<Option<SystemTime>>,
+}
+
+impl AppState {
+    pub fn new(
+        store: Box<dyn Store>,
+        history: History,
+        trash: Trash,
+        backups: Backups,
+    ) -> Result<Self> {
+        let state = AppState {
+            store,
+            history,
+            trash,
+            backups,
+            write_lock: Mutex::new(()),
+            collections: RwLock::new(Vec::new()),
+            search_index: RwLock::new(SearchIndex::default()),
+            loaded_at: Mutex::new(None),
+        };
+        state.refresh()?;
+        Ok(state)
+    }
+
+    /// Serializes read-modify-write cycles against the store. Hold the guard from
+    /// the first read until the write completes, then call `refresh`.
+    pub fn lock(&self) -> MutexGuard<'_, ()> {
+        // The guarded data is `()`, so a panic elsewhere cannot leave it inconsistent.
+        self.write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The cached collections, in display order.
+    pub fn collections(&self) -> RwLockReadGuard<'_, Vec<Collection>> {
+        self.collections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<Collection> {
+        self.collections()
+            .iter()
+            .find(|item| item.id == id)
+            .cloned()
+    }
+
+    /// Adds a change to the history. The change is already stored, so a failure
+    /// here is logged rather than failing the request.
+    pub fn record(
+        &self,
+        action: Action,
+        author: &str,
+        before: Option<&Collection>,
+        after: Option<&Collection>,
+    ) {
+        let result = Revision::new(action, author, before, after)
+            .and_then(|revision| self.history.record(&revision));
+        if let Err(error) = result {
+            eprintln!("Failed to record history: {}", error);
+        }
+    }
+
+    /// Snapshots the store, e.g. before a destructive change. Callers must hold
+    /// the state lock so the snapshot matches what the change starts from.
+    pub fn back_up(&self, reason: &str) -> Result<Snapshot> {
+        let snapshot = self.backups.create(&*self.store, reason)?;
+        println!("Backed up projects data to \"{}\"", snapshot.name);
+        Ok(snapshot)
+    }
+
+    /// Takes a scheduled snapshot unless nothing has changed since the last one.
+    fn back_up_if_changed(&self) -> Result<bool> {
+        let _guard = self.lock();
+        let changed_at = self.store.changed_at()?.map(DateTime::<Utc>::from);
+        if let (Some(changed_at), Some(latest)) = (changed_at, self.backups.latest()?) {
+            if changed_at <= latest {
+                return Ok(false);
+            }
+        }
+        self.back_up(SCHEDULED)?;
+        Ok(true)
+    }
+
+    /// Publishes every scheduled collection whose `publish_at` has passed and
+    /// returns them.
+    pub fn publish_due(&self) -> Result<Vec<Collection>> {
+        let _guard = self.lock();
+        let now = Utc::now();
+        let modified = timestamp();
+        let due: Vec<(Collection, Collection)> = self
+            .store
+            .list()?
+            .into_iter()
+            .filter(|collection| {
+                collection.status == Status::Scheduled
+                    && collection
+                        .publish_at
+                        .as_deref()
+                        .and_then(parse_timestamp)
+                        .is_some_and(|at| at <= now)
+            })
+            .map(|collection| {
+                let mut published = collection.clone();
+                published.status = Status::Published;
+                published.last_modified = modified.clone();
+                published.revision = collection.revision.saturating_add(1);
+                (collection, published)
+            })
+            .collect();
+        if due.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.store
+            .update_all(due.iter().map(|(_, published)| published.clone()).collect())?;
+        self.invalidate();
+        for (before, after) in &due {
+            self.record(Action::Publish, SCHEDULER, Some(before), Some(after));
+        }
+        Ok(due.into_iter().map(|(_, published)| published).collect())
+    }
+
+    pub fn search(&self, query: &str, limit: usize, published_only: bool) -> Vec<Hit> {
+        self.search_index
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .search(query, limit, published_only)
+    }
+
+    /// Reloads the cache from the store.
+    pub fn refresh(&self) -> Result<()> {
+        let changed_at = self.store.changed_at()?;
+        let mut collections = self.store.list()?;
+        // Stores keep this order already unless the file was edited by hand.
+        collections.sort_by_key(|collection| collection.position);
+        *self
+            .search_index
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = SearchIndex::build(&collections);
+        *self
+            .collections
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = collections;
+        *self
+            .loaded_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = changed_at;
+        Ok(())
+    }
+
+    /// Drops the cached data after a write. If reloading fails the watcher
+    /// retries, since the store's change time no longer matches the cache.
+    pub fn invalidate(&self) {
+        if let Err(error) = self.refresh() {
+            eprintln!("Failed to reload projects data: {}", error);
+        }
+    }
+
+    /// Reloads the cache if the store has changed since it was last read.
+    fn refresh_if_changed(&self) -> Result<bool> {
+        let _guard = self.lock();
+        let changed_at = self.store.changed_at()?;
+        if changed_at
+            == *self
+                .loaded_at
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        {
+            return Ok(false);
+        }
+        self.refresh()?;
+        Ok(true)
+    }
+}
+
+/// Polls the store for changes made outside the server, e.g. a hand-edited
+/// projects file, and reloads the cache when one is seen.
+pub fn watch_store(state: Data<AppState>) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            match state.refresh_if_changed() {
+                Ok(true) => println!("Projects data changed on disk, cache reloaded."),
+                Ok(false) => {}
+                Err(error) => eprintln!("Failed to reload projects data: {}", error),
+            }
+        }
+    });
+}
+
+/// Permanently removes collections that have been in the trash longer than the
+/// retention period, once at startup and then every hour.
+pub fn purge_trash(state: Data<AppState>) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let purged = {
+                let _guard = state.lock();
+                state.trash.purge()
+            };
+            match purged {
+                Ok(purged) => {
+                    for deleted in purged {
+                        println!("Purged \"{}\" from the trash", deleted.collection.title);
+                    }
+                }
+                Err(error) => eprintln!("Failed to purge the trash: {}", error),
+            }
+        }
+    });
+}
+
+/// Snapshots the store every `minutes` minutes while it keeps changing. 0 turns
+/// scheduled backups off.
+pub fn schedule_backups(state: Data<AppState>, minutes: u16) {
+    if minutes == 0 {
+        return;
+    }
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(Duration::from_secs(u64::from(minutes) * 60));
+        // The first tick is immediate, and startup has just taken a snapshot.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(error) = state.back_up_if_changed() {
+                eprintln!("Scheduled backup failed: {}", error);
+            }
+        }
+    });
+}
+
+/// Publishes scheduled collections once they are due, checking every minute.
+pub fn publish_scheduled(state: Data<AppState>) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(SCHEDULE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match state.publish_due() {
+                Ok(published) => {
+                    for collection in published {
+                        println!("Published \"{}\" as scheduled", collection.title);
+                    }
+                }
+                Err(error) => eprintln!("Failed to publish scheduled collections: {}", error),
+            }
+        }
+    });
+}
+
+/// State over `collections`, kept in a projects file inside `dir` with the
+/// history, trash and backups next to it.
+#[cfg(test)]
+impl AppState {
+    pub fn in_temp_dir(dir: &crate::core::utils::TempDir, collections: Vec<Collection>) -> Self {
+        use crate::core::{backup::Retention, data::write_local_db, store::JsonStore};
+
+        let path = dir.path("projects.json");
+        write_local_db(&path, collections).unwrap();
+        let retention = Retention {
+            event_days: 1,
+            ..Retention::NONE
+        };
+        AppState::new(
+            Box::new(JsonStore::new(&path)),
+            History::new(&dir.path("history.jsonl")),
+            Trash::new(&dir.path("trash.json"), 30),
+            Backups::new(&dir.path("backup"), "projects", retention),
+        )
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::AppState;
+    use crate::core::{
+        data::{write_local_db, Collection, Status},
+        utils::TempDir,
+    };
+
+    #[test]
+    fn picks_up_edits_to_the_projects_file() {
+        let dir = TempDir::create();
+        let mut collection = Collection::default(Vec::new());
+        collection.status = Status::Published;
+        let state = AppState::in_temp_dir(&dir, vec![collection.clone()]);
+        assert!(!state.refresh_if_changed().unwrap());
+        assert!(state.search("espresso", 10, true).is_empty());
+
+        // Leave the modification time room to move on coarse filesystems.
+        thread::sleep(Duration::from_millis(20));
+        collection.title = "Espresso menu".to_string();
+        write_local_db(&dir.path("projects.json"), vec![collection]).unwrap();
+        assert!(state.refresh_if_changed().unwrap());
+        assert_eq!(state.collections()[0].title, "Espresso menu");
+        assert_eq!(state.search("espresso", 10, true)[0].title, "Espresso menu");
+        assert!(!state.refresh_if_changed().unwrap());
+    }
+}